@@ -0,0 +1,181 @@
+use tox::core::{ClientId};
+use std::collections::{HashMap};
+use std::io::{File, BufferedReader, IoResult, Truncate};
+use std::io::fs::{rename};
+use profile::{open_private, create_dir};
+
+/// What we know about a friend that they didn't tell us.
+pub struct Contact {
+    /// The name we show instead of the one they chose. Empty if not set.
+    pub alias: String,
+    pub note: String,
+    /// Whether we're not notified about their messages.
+    pub muted: bool,
+    /// The address we've sent a friend request to. Empty if they've sent us one.
+    pub address: String,
+}
+
+impl Contact {
+    fn new() -> Contact {
+        Contact {
+            alias: String::new(),
+            note: String::new(),
+            muted: false,
+            address: String::new(),
+        }
+    }
+}
+
+/// Aliases, notes and mute settings of our friends.
+///
+/// The file has a line of the form `<client id> alias <alias>` or
+/// `<client id> note <note>` for every alias and note, `<client id> mute yes`
+/// for every muted friend and `<client id> address <address>` for every friend we've
+/// sent a request to.
+pub struct AddressBook {
+    contacts: HashMap<String, Contact>,
+    path: Path,
+}
+
+impl AddressBook {
+    /// Loads the address book at `path`. A missing file is treated like an empty one.
+    pub fn load(path: Path) -> IoResult<AddressBook> {
+        let mut contacts = HashMap::new();
+        if path.exists() {
+            let file = try!(File::open(&path));
+            for line in BufferedReader::new(file).lines() {
+                let line = try!(line);
+                let line = line.as_slice().trim_right_chars('\n');
+                let mut parts = line.splitn(' ', 2);
+                let (id, key, val) = match (parts.next(), parts.next(), parts.next()) {
+                    (Some(id), Some(key), Some(val)) => (id, key, val),
+                    _ => continue,
+                };
+                let contact = contacts.find_or_insert_with(id.to_string(), |_| {
+                    Contact::new()
+                });
+                match key {
+                    "alias" => contact.alias = val.to_string(),
+                    "note" => contact.note = val.to_string(),
+                    "mute" => contact.muted = val == "yes",
+                    "address" => contact.address = val.to_string(),
+                    _ => { },
+                }
+            }
+        }
+        Ok(AddressBook {
+            contacts: contacts,
+            path: path,
+        })
+    }
+
+    pub fn path<'a>(&'a self) -> &'a Path {
+        &self.path
+    }
+
+    pub fn get<'a>(&'a self, id: &ClientId) -> Option<&'a Contact> {
+        self.contacts.find(&id.to_string())
+    }
+
+    fn contact<'a>(&'a mut self, id: &ClientId) -> &'a mut Contact {
+        self.contacts.find_or_insert_with(id.to_string(), |_| Contact::new())
+    }
+
+    /// Sets the alias of `id`. An empty alias removes it.
+    pub fn set_alias(&mut self, id: &ClientId, alias: String) {
+        self.contact(id).alias = alias;
+        self.remove_empty(id);
+    }
+
+    /// Sets the note of `id`. An empty note removes it.
+    pub fn set_note(&mut self, id: &ClientId, note: String) {
+        self.contact(id).note = note;
+        self.remove_empty(id);
+    }
+
+    pub fn set_muted(&mut self, id: &ClientId, muted: bool) {
+        self.contact(id).muted = muted;
+        self.remove_empty(id);
+    }
+
+    /// Remembers that we've sent a friend request to `id` at `address`.
+    pub fn set_address(&mut self, id: &ClientId, address: String) {
+        self.contact(id).address = address;
+    }
+
+    /// Returns the addresses we've sent friend requests to.
+    pub fn addresses(&self) -> Vec<String> {
+        self.contacts.values().filter(|c| c.address.len() > 0)
+                     .map(|c| c.address.clone()).collect()
+    }
+
+    fn remove_empty(&mut self, id: &ClientId) {
+        let key = id.to_string();
+        let empty = match self.contacts.find(&key) {
+            Some(c) => {
+                c.alias.len() == 0 && c.note.len() == 0 && !c.muted
+                    && c.address.len() == 0
+            },
+            None => false,
+        };
+        if empty {
+            self.contacts.remove(&key);
+        }
+    }
+
+    /// Writes the address book to its file. Notes are private, so only the user may
+    /// read it.
+    pub fn save(&self) -> IoResult<()> {
+        try!(create_dir(&self.path.dir_path()));
+        let tmp = self.path.with_extension("tmp");
+        {
+            let mut file = try!(open_private(&tmp, Truncate));
+            for (id, contact) in self.contacts.iter() {
+                if contact.alias.len() > 0 {
+                    try!(writeln!(file, "{} alias {}", id, contact.alias));
+                }
+                if contact.note.len() > 0 {
+                    try!(writeln!(file, "{} note {}", id, contact.note));
+                }
+                if contact.muted {
+                    try!(writeln!(file, "{} mute yes", id));
+                }
+                if contact.address.len() > 0 {
+                    try!(writeln!(file, "{} address {}", id, contact.address));
+                }
+            }
+            try!(file.fsync());
+        }
+        rename(&tmp, &self.path)
+    }
+}
+
+#[test]
+fn test_mute_round_trip() {
+    use std::io::{TempDir};
+
+    let dir = TempDir::new("stannis").unwrap();
+    let path = dir.path().join("addressbook");
+    let muted: ClientId = from_str("951C88B7E75C867418ACDB5D273821372BB5BD652740BCDF\
+                                    623A4FA293E75D2F").unwrap();
+    let other: ClientId = from_str("04119E835DF3E78BACF0F84235B300546AF8B936F035185E\
+                                    2A8E9E0A67C8924F").unwrap();
+
+    let mut book = AddressBook::load(path.clone()).unwrap();
+    book.set_muted(&muted, true);
+    book.set_alias(&other, "bob".to_string());
+    book.set_address(&other, "address".to_string());
+    book.save().unwrap();
+
+    let mut book = AddressBook::load(path.clone()).unwrap();
+    assert!(book.get(&muted).unwrap().muted);
+    assert_eq!(book.get(&muted).unwrap().alias.as_slice(), "");
+    assert!(!book.get(&other).unwrap().muted);
+    book.set_muted(&muted, false);
+    book.save().unwrap();
+
+    let book = AddressBook::load(path).unwrap();
+    assert!(book.get(&muted).is_none());
+    assert_eq!(book.get(&other).unwrap().alias.as_slice(), "bob");
+    assert_eq!(book.addresses(), vec!["address".to_string()]);
+}
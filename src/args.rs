@@ -0,0 +1,88 @@
+use getopts::{optopt, optflag, optmulti, getopts, usage, OptGroup};
+use config;
+use config::{Node};
+use profile;
+
+use std::io::{stderr};
+use std::os::{set_exit_status};
+
+static VERSION: &'static str = "0.0.1";
+
+pub struct Options {
+    pub profile: Path,
+    pub config: Path,
+    /// If not empty, we bootstrap from these nodes only.
+    pub nodes: Vec<Node>,
+    pub ipv6: bool,
+    pub print_address: bool,
+    /// Read commands from stdin and write events to stdout instead of using the
+    /// terminal.
+    pub headless: bool,
+}
+
+fn opts() -> Vec<OptGroup> {
+    vec!(
+        optopt("p", "profile", "load the profile from PATH", "PATH"),
+        optopt("c", "config", "read the configuration from PATH", "PATH"),
+        optmulti("n", "node", "bootstrap from NODE instead of the configured nodes",
+                 "'HOST PORT KEY'"),
+        optflag("4", "ipv4", "don't use IPv6"),
+        optflag("", "print-address", "print our address and exit"),
+        optflag("", "headless", "read commands from stdin and write events as JSON"),
+        optflag("V", "version", "print the version and exit"),
+        optflag("h", "help", "print this help and exit"),
+    )
+}
+
+/// Parses the command line.
+///
+/// Returns `None` if the program should exit right away. The exit status is set
+/// accordingly.
+pub fn parse(args: &[String]) -> Option<Options> {
+    let opts = opts();
+    let prog = args[0].as_slice();
+    let matches = match getopts(args.tail(), opts.as_slice()) {
+        Ok(m) => m,
+        Err(f) => {
+            (writeln!(stderr(), "{}: {}", prog, f)).ok();
+            set_exit_status(2);
+            return None;
+        },
+    };
+    if matches.opt_present("h") {
+        println!("{}", usage(format!("Usage: {} [OPTIONS]", prog).as_slice(),
+                             opts.as_slice()));
+        return None;
+    }
+    if matches.opt_present("V") {
+        println!("stannis {}", VERSION);
+        return None;
+    }
+    if matches.free.len() > 0 {
+        (writeln!(stderr(), "{}: unexpected argument `{}`", prog,
+                  matches.free.get(0))).ok();
+        set_exit_status(2);
+        return None;
+    }
+    let mut nodes = Vec::new();
+    for s in matches.opt_strs("n").iter() {
+        match Node::parse(s.as_slice()) {
+            Ok(n) => nodes.push(n),
+            Err(e) => {
+                (writeln!(stderr(), "{}: invalid node `{}`: {}", prog, s, e)).ok();
+                set_exit_status(2);
+                return None;
+            },
+        }
+    }
+    Some(Options {
+        profile: matches.opt_str("p").map(|p| Path::new(p))
+                        .unwrap_or_else(|| profile::default_path()),
+        config: matches.opt_str("c").map(|p| Path::new(p))
+                       .unwrap_or_else(|| config::default_path()),
+        nodes: nodes,
+        ipv6: !matches.opt_present("4"),
+        print_address: matches.opt_present("print-address"),
+        headless: matches.opt_present("headless"),
+    })
+}
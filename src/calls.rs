@@ -0,0 +1,341 @@
+use std::io::{File};
+use std::io::timer::{Timer};
+use std::comm::{Empty};
+
+/// Number of samples in a frame of audio: 20 ms of mono audio at 48 kHz.
+pub static FRAME_SAMPLES: uint = 960;
+static FRAME_MS: u64 = 20;
+
+/// Where the audio of a call comes from and where the other side's audio goes.
+pub trait MediaBackend {
+    /// Called when a call starts.
+    fn open(&mut self) -> Result<(), String>;
+    /// Called when the call ends.
+    fn close(&mut self);
+    /// Returns the next frame we send or `None` if there's nothing to send.
+    fn capture(&mut self) -> Option<Vec<i16>>;
+    /// Plays a frame we received.
+    fn play(&mut self, frame: &[i16]);
+}
+
+/// Sends silence and drops what it receives.
+pub struct NullBackend;
+
+impl MediaBackend for NullBackend {
+    fn open(&mut self) -> Result<(), String> {
+        Ok(())
+    }
+
+    fn close(&mut self) { }
+
+    fn capture(&mut self) -> Option<Vec<i16>> {
+        Some(Vec::from_elem(FRAME_SAMPLES, 0i16))
+    }
+
+    fn play(&mut self, _: &[i16]) { }
+}
+
+/// Reads the audio we send from a file and writes the audio we receive to a file.
+///
+/// Both files contain raw 16 bit little endian samples, mono at 48 kHz. Without an
+/// input file we send nothing, without an output file what we receive is dropped.
+pub struct FileBackend {
+    input: Option<Path>,
+    output: Option<Path>,
+    reader: Option<File>,
+    writer: Option<File>,
+}
+
+impl FileBackend {
+    pub fn new(input: Option<Path>, output: Option<Path>) -> FileBackend {
+        FileBackend {
+            input: input,
+            output: output,
+            reader: None,
+            writer: None,
+        }
+    }
+}
+
+impl MediaBackend for FileBackend {
+    fn open(&mut self) -> Result<(), String> {
+        self.reader = match self.input {
+            Some(ref p) => match File::open(p) {
+                Ok(f) => Some(f),
+                Err(e) => return Err(format!("{}: {}", p.display(), e)),
+            },
+            None => None,
+        };
+        self.writer = match self.output {
+            Some(ref p) => match File::create(p) {
+                Ok(f) => Some(f),
+                Err(e) => return Err(format!("{}: {}", p.display(), e)),
+            },
+            None => None,
+        };
+        Ok(())
+    }
+
+    fn close(&mut self) {
+        self.reader = None;
+        self.writer = None;
+    }
+
+    fn capture(&mut self) -> Option<Vec<i16>> {
+        let reader = match self.reader {
+            Some(ref mut r) => r,
+            None => return None,
+        };
+        let mut frame = Vec::with_capacity(FRAME_SAMPLES);
+        for _ in range(0, FRAME_SAMPLES) {
+            match reader.read_le_i16() {
+                Ok(s) => frame.push(s),
+                Err(_) => break,
+            }
+        }
+        if frame.len() > 0 { Some(frame) } else { None }
+    }
+
+    fn play(&mut self, frame: &[i16]) {
+        match self.writer {
+            Some(ref mut w) => {
+                for &s in frame.iter() {
+                    if w.write_le_i16(s).is_err() {
+                        break;
+                    }
+                }
+            },
+            None => { },
+        }
+    }
+}
+
+#[deriving(PartialEq, Show)]
+pub enum CallState {
+    /// We're waiting for the friend to answer.
+    Calling,
+    /// The friend is waiting for us to answer.
+    Ringing,
+    InCall,
+}
+
+impl CallState {
+    pub fn fmt(self) -> &'static str {
+        match self {
+            Calling => "calling",
+            Ringing => "ringing",
+            InCall => "in call",
+        }
+    }
+}
+
+pub struct Call {
+    /// The number toxav uses for the call.
+    pub index: i32,
+    pub friend: i32,
+    pub state: CallState,
+}
+
+/// The calls we're in or that are being set up.
+///
+/// Only one call can be in progress at a time. It's the one that uses the backend.
+pub struct Calls {
+    calls: Vec<Call>,
+    backend: Box<MediaBackend>,
+    /// The backend that replaces `backend` once the call in progress ends.
+    pending: Option<Box<MediaBackend>>,
+}
+
+impl Calls {
+    pub fn new(backend: Box<MediaBackend>) -> Calls {
+        Calls {
+            calls: Vec::new(),
+            backend: backend,
+            pending: None,
+        }
+    }
+
+    /// Replaces the backend. A call in progress keeps using the old one until it
+    /// ends.
+    pub fn set_backend(&mut self, backend: Box<MediaBackend>) {
+        if self.active().is_some() {
+            self.pending = Some(backend);
+        } else {
+            self.backend = backend;
+        }
+    }
+
+    pub fn get<'a>(&'a self, index: i32) -> Option<&'a Call> {
+        self.calls.iter().find(|c| c.index == index)
+    }
+
+    pub fn with_friend<'a>(&'a self, friend: i32) -> Option<&'a Call> {
+        self.calls.iter().find(|c| c.friend == friend)
+    }
+
+    /// Returns the call in progress.
+    pub fn active<'a>(&'a self) -> Option<&'a Call> {
+        self.calls.iter().find(|c| c.state == InCall)
+    }
+
+    /// Returns the call in progress or, if there's none, a call we've started.
+    pub fn current<'a>(&'a self) -> Option<&'a Call> {
+        self.active().or_else(|| self.calls.iter().find(|c| c.state == Calling))
+    }
+
+    /// Returns the first call a friend has started that we haven't answered yet.
+    pub fn incoming<'a>(&'a self) -> Option<&'a Call> {
+        self.calls.iter().find(|c| c.state == Ringing)
+    }
+
+    /// Adds a call we've started.
+    pub fn calling(&mut self, index: i32, friend: i32) {
+        self.calls.push(Call { index: index, friend: friend, state: Calling });
+    }
+
+    /// Adds a call a friend has started.
+    pub fn ringing(&mut self, index: i32, friend: i32) {
+        self.calls.push(Call { index: index, friend: friend, state: Ringing });
+    }
+
+    /// Starts the media of the call `index` once both sides have agreed.
+    pub fn start(&mut self, index: i32) -> Result<(), String> {
+        if self.active().is_some() {
+            return Err("already in a call".to_string());
+        }
+        if self.get(index).is_none() {
+            return Err(format!("unknown call {}", index));
+        }
+        try!(self.backend.open());
+        for c in self.calls.mut_iter().filter(|c| c.index == index) {
+            c.state = InCall;
+        }
+        Ok(())
+    }
+
+    /// Forgets the call `index` and stops its media. Returns the call.
+    pub fn end(&mut self, index: i32) -> Option<Call> {
+        let pos = match self.calls.iter().position(|c| c.index == index) {
+            Some(pos) => pos,
+            None => return None,
+        };
+        let call = self.calls.remove(pos).unwrap();
+        if call.state == InCall {
+            self.backend.close();
+            match self.pending.take() {
+                Some(backend) => self.backend = backend,
+                None => { },
+            }
+        }
+        Some(call)
+    }
+
+    /// Returns the next frame for the call in progress.
+    pub fn capture(&mut self) -> Option<Vec<i16>> {
+        if self.active().is_none() {
+            return None;
+        }
+        self.backend.capture()
+    }
+
+    /// Plays a frame we've received in the call `index`.
+    pub fn play(&mut self, index: i32, frame: &[i16]) {
+        match self.get(index) {
+            Some(c) if c.state == InCall => { },
+            _ => return,
+        }
+        self.backend.play(frame);
+    }
+}
+
+/// Starts a task that ticks every 20 ms while it's turned on. That's when we send a
+/// frame of audio.
+pub fn spawn_clock() -> (Sender<bool>, Receiver<()>) {
+    let (on_tx, on_rx) = channel();
+    let (tick_tx, tick_rx) = channel();
+    spawn(proc() {
+        let mut timer = Timer::new().unwrap();
+        let mut on = false;
+        loop {
+            if !on {
+                match on_rx.recv_opt() {
+                    Ok(b) => on = b,
+                    Err(_) => break,
+                }
+                continue;
+            }
+            timer.sleep(FRAME_MS);
+            match on_rx.try_recv() {
+                Ok(b) => on = b,
+                Err(Empty) => { },
+                Err(_) => break,
+            }
+            if on && tick_tx.send_opt(()).is_err() {
+                break;
+            }
+        }
+    });
+    (on_tx, tick_rx)
+}
+
+#[test]
+fn test_calls() {
+    let mut calls = Calls::new(box NullBackend);
+    calls.ringing(0, 3);
+    assert_eq!(calls.with_friend(3).map(|c| c.state), Some(Ringing));
+    assert_eq!(calls.incoming().map(|c| c.index), Some(0));
+    assert!(calls.capture().is_none());
+    calls.start(0).unwrap();
+    assert!(calls.incoming().is_none());
+    assert_eq!(calls.active().map(|c| c.friend), Some(3));
+    assert_eq!(calls.capture().map(|f| f.len()), Some(FRAME_SAMPLES));
+
+    calls.calling(1, 4);
+    assert!(calls.start(1).is_err());
+    assert_eq!(calls.end(1).map(|c| c.state), Some(Calling));
+
+    assert_eq!(calls.end(0).map(|c| c.friend), Some(3));
+    assert!(calls.active().is_none());
+    assert!(calls.end(0).is_none());
+    assert!(calls.capture().is_none());
+}
+
+#[test]
+fn test_file_backend() {
+    use std::io::{TempDir};
+
+    let dir = TempDir::new("stannis").unwrap();
+    let input = dir.path().join("in");
+    let output = dir.path().join("out");
+    {
+        let mut f = File::create(&input).unwrap();
+        for i in range(0i16, 10) {
+            f.write_le_i16(i).unwrap();
+        }
+    }
+    let backend = FileBackend::new(Some(input), Some(output.clone()));
+    let mut calls = Calls::new(box backend);
+    calls.calling(5, 1);
+    calls.play(5, [7, 7]);
+    calls.start(5).unwrap();
+    assert_eq!(calls.capture(), Some(Vec::from_fn(10, |i| i as i16)));
+    assert_eq!(calls.capture(), None);
+    calls.play(5, [1, 2, -1]);
+    calls.end(5);
+    let data = File::open(&output).read_to_end().unwrap();
+    assert_eq!(data, vec!(1u8, 0, 2, 0, 0xFF, 0xFF));
+}
+
+#[test]
+fn test_set_backend() {
+    let mut calls = Calls::new(box FileBackend::new(None, None));
+    calls.ringing(0, 3);
+    calls.start(0).unwrap();
+    calls.set_backend(box NullBackend);
+    // The call keeps using the file backend, which has nothing to send.
+    assert!(calls.capture().is_none());
+    calls.end(0);
+    calls.ringing(1, 3);
+    calls.start(1).unwrap();
+    assert_eq!(calls.capture().map(|f| f.len()), Some(FRAME_SAMPLES));
+}
@@ -0,0 +1,159 @@
+use nc = ncurses;
+use colors::*;
+use term::cwidth::{CharWidth};
+
+struct Message {
+    from: String,
+    text: String,
+    action: bool,
+    /// The receipt tox gave us for a message we sent that hasn't been read yet.
+    receipt: Option<u32>,
+}
+
+struct Line {
+    text: String,
+    /// Number of bytes at the start of the line that belong to the sender's name.
+    name: uint,
+    pending: bool,
+}
+
+impl Message {
+    /// Splits the message into lines that are at most `width` columns wide.
+    fn wrap(&self, width: uint) -> Vec<Line> {
+        let (text, name) = if self.action {
+            (format!("* {} {}", self.from, self.text), 2 + self.from.len())
+        } else {
+            (format!("{}: {}", self.from, self.text), self.from.len())
+        };
+        let mut lines = Vec::new();
+        let mut line = String::new();
+        let mut line_width = 0;
+        for c in text.as_slice().chars() {
+            if c == '\n' {
+                lines.push(line);
+                line = String::new();
+                line_width = 0;
+                continue;
+            }
+            let w = match c.width() {
+                Some(w) => w,
+                None => continue,
+            };
+            if line_width + w > width && line.len() > 0 {
+                lines.push(line);
+                line = String::new();
+                line_width = 0;
+            }
+            line.push_char(c);
+            line_width += w;
+        }
+        lines.push(line);
+        let mut name = name;
+        lines.move_iter().map(|text| {
+            let n = if name > text.len() { text.len() } else { name };
+            name -= n;
+            Line { text: text, name: n, pending: self.receipt.is_some() }
+        }).collect()
+    }
+}
+
+/// The message history of a conversation.
+pub struct Chat {
+    messages: Vec<Message>,
+    /// Number of lines we've scrolled up from the bottom.
+    scroll: uint,
+}
+
+impl Chat {
+    pub fn new() -> Chat {
+        Chat {
+            messages: Vec::new(),
+            scroll: 0,
+        }
+    }
+
+    pub fn push(&mut self, from: &str, text: String) {
+        self.messages.push(Message {
+            from: from.to_string(),
+            text: text,
+            action: false,
+            receipt: None,
+        });
+    }
+
+    /// Adds a message we sent that's shown as pending until `delivered(receipt)`.
+    pub fn push_sent(&mut self, from: &str, text: String, receipt: u32) {
+        self.messages.push(Message {
+            from: from.to_string(),
+            text: text,
+            action: false,
+            receipt: Some(receipt),
+        });
+    }
+
+    pub fn push_action(&mut self, from: &str, text: String) {
+        self.messages.push(Message {
+            from: from.to_string(),
+            text: text,
+            action: true,
+            receipt: None,
+        });
+    }
+
+    /// Marks the message with `receipt` as delivered.
+    pub fn delivered(&mut self, receipt: u32) {
+        match self.messages.mut_iter().rev().find(|m| m.receipt == Some(receipt)) {
+            Some(m) => m.receipt = None,
+            None => { },
+        }
+    }
+
+    pub fn scroll_up(&mut self, lines: uint) {
+        self.scroll += lines;
+    }
+
+    pub fn scroll_down(&mut self, lines: uint) {
+        if lines > self.scroll {
+            self.scroll = 0;
+        } else {
+            self.scroll -= lines;
+        }
+    }
+
+    /// Draws the history into the `height` lines starting at `top`.
+    pub fn draw(&mut self, top: i32, height: uint) {
+        let width = nc::COLS as uint;
+        // Bottom line first.
+        let mut lines = Vec::new();
+        for msg in self.messages.iter().rev() {
+            lines.extend(msg.wrap(width).move_iter().rev());
+            if lines.len() >= self.scroll + height {
+                break;
+            }
+        }
+        if self.scroll + height > lines.len() {
+            self.scroll = if lines.len() > height { lines.len() - height } else { 0 };
+        }
+
+        let mut y = top + height as i32 - 1;
+        for line in lines.iter().skip(self.scroll).take(height) {
+            nc::move(y, 0);
+            bold!(COLOR_PAIR_HEADER);
+            nc::addstr(line.text.as_slice().slice_to(line.name));
+            if line.pending {
+                normal!(COLOR_PAIR_OFFLINE);
+            } else {
+                normal!(COLOR_PAIR_DEFAULT);
+            }
+            nc::addstr(line.text.as_slice().slice_from(line.name));
+            normal!(COLOR_PAIR_DEFAULT);
+            nc::clrtoeol();
+            y -= 1;
+        }
+        while y >= top {
+            nc::move(y, 0);
+            nc::clrtoeol();
+            y -= 1;
+        }
+    }
+}
@@ -1,5 +1,7 @@
 #![macro_escape]
 
+use nc = ncurses;
+
 // Don't change the first 16 colors
 pub static COLOR_BACKGROUND:  i16 = 16;
 pub static COLOR_HEADERS:     i16 = 17;
@@ -8,6 +10,14 @@ pub static COLOR_SEPARATOR:   i16 = 19;
 pub static COLOR_SELECTED_BG: i16 = 20;
 pub static COLOR_STATUS_BG:   i16 = 21;
 pub static COLOR_PROMPT_BG:   i16 = 22;
+pub static COLOR_ONLINE:      i16 = 23;
+pub static COLOR_AWAY:        i16 = 24;
+pub static COLOR_BUSY:        i16 = 25;
+pub static COLOR_OFFLINE:     i16 = 26;
+pub static COLOR_MATCH_BG:    i16 = 27;
+pub static COLOR_INFO_BG:     i16 = 28;
+pub static COLOR_WARNING_BG:  i16 = 29;
+pub static COLOR_MENTION:     i16 = 30;
 
 pub static COLOR_PAIR_DEFAULT:   i16 = 1;
 pub static COLOR_PAIR_HEADER:    i16 = 2;
@@ -15,6 +25,71 @@ pub static COLOR_PAIR_SEPARATOR: i16 = 3;
 pub static COLOR_PAIR_SELECTED:  i16 = 4;
 pub static COLOR_PAIR_STATUS:    i16 = 5;
 pub static COLOR_PAIR_PROMPT:    i16 = 6;
+pub static COLOR_PAIR_ONLINE:    i16 = 7;
+pub static COLOR_PAIR_AWAY:      i16 = 8;
+pub static COLOR_PAIR_BUSY:      i16 = 9;
+pub static COLOR_PAIR_OFFLINE:   i16 = 10;
+pub static COLOR_PAIR_MATCH:     i16 = 11;
+pub static COLOR_PAIR_INFO:      i16 = 12;
+pub static COLOR_PAIR_WARNING:   i16 = 13;
+pub static COLOR_PAIR_MENTION:   i16 = 14;
+
+static PALETTE: [(&'static str, i16, u32), ..15] = [
+    ("background",  COLOR_BACKGROUND,  0x1C1C1C),
+    ("headers",     COLOR_HEADERS,     0xA381A6),
+    ("entry",       COLOR_ENTRY,       0xDDDDDD),
+    ("separator",   COLOR_SEPARATOR,   0x2B3336),
+    ("selected-bg", COLOR_SELECTED_BG, 0x404040),
+    ("status-bg",   COLOR_STATUS_BG,   0x612020),
+    ("prompt-bg",   COLOR_PROMPT_BG,   0x362065),
+    ("online",      COLOR_ONLINE,      0x5FAF5F),
+    ("away",        COLOR_AWAY,        0xD7AF5F),
+    ("busy",        COLOR_BUSY,        0xD75F5F),
+    ("offline",     COLOR_OFFLINE,     0x606060),
+    ("match-bg",    COLOR_MATCH_BG,    0x3A3A20),
+    ("info-bg",     COLOR_INFO_BG,     0x203A50),
+    ("warning-bg",  COLOR_WARNING_BG,  0x5A4A10),
+    ("mention",     COLOR_MENTION,     0xFF875F),
+];
+
+/// Returns the color with the name `name` in the configuration file.
+pub fn by_name(name: &str) -> Option<i16> {
+    PALETTE.iter().find(|&&(n, _, _)| n == name).map(|&(_, c, _)| c)
+}
+
+fn init_rgb(color: i16, rgb: u32) {
+    let r = ((rgb >> 16) & 0xFF) as i16;
+    let g = ((rgb >>  8) & 0xFF) as i16;
+    let b = ((rgb >>  0) & 0xFF) as i16;
+    nc::init_color(color, r * 4, g * 4, b * 4);
+}
+
+/// Initializes the palette and the color pairs.
+///
+/// `overrides` contains `(color, 0xRRGGBB)` tuples that replace the default colors.
+pub fn init(overrides: &[(i16, u32)]) {
+    for &(_, color, rgb) in PALETTE.iter() {
+        init_rgb(color, rgb);
+    }
+    for &(color, rgb) in overrides.iter() {
+        init_rgb(color, rgb);
+    }
+
+    nc::init_pair(COLOR_PAIR_DEFAULT,   COLOR_ENTRY,     COLOR_BACKGROUND);
+    nc::init_pair(COLOR_PAIR_HEADER,    COLOR_HEADERS,   COLOR_BACKGROUND);
+    nc::init_pair(COLOR_PAIR_SEPARATOR, COLOR_SEPARATOR, COLOR_BACKGROUND);
+    nc::init_pair(COLOR_PAIR_SELECTED,  COLOR_ENTRY,     COLOR_SELECTED_BG);
+    nc::init_pair(COLOR_PAIR_STATUS,    COLOR_ENTRY,     COLOR_STATUS_BG);
+    nc::init_pair(COLOR_PAIR_PROMPT,    COLOR_ENTRY,     COLOR_PROMPT_BG);
+    nc::init_pair(COLOR_PAIR_ONLINE,    COLOR_ONLINE,    COLOR_BACKGROUND);
+    nc::init_pair(COLOR_PAIR_AWAY,      COLOR_AWAY,      COLOR_BACKGROUND);
+    nc::init_pair(COLOR_PAIR_BUSY,      COLOR_BUSY,      COLOR_BACKGROUND);
+    nc::init_pair(COLOR_PAIR_OFFLINE,   COLOR_OFFLINE,   COLOR_BACKGROUND);
+    nc::init_pair(COLOR_PAIR_MATCH,     COLOR_ENTRY,     COLOR_MATCH_BG);
+    nc::init_pair(COLOR_PAIR_INFO,      COLOR_ENTRY,     COLOR_INFO_BG);
+    nc::init_pair(COLOR_PAIR_WARNING,   COLOR_ENTRY,     COLOR_WARNING_BG);
+    nc::init_pair(COLOR_PAIR_MENTION,   COLOR_MENTION,   COLOR_BACKGROUND);
+}
 
 #[macro_escape]
 macro_rules! bold {
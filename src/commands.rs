@@ -1,142 +1,1174 @@
-use tox::core::{Address};
+use tox::core::{Address, UserStatus, UserStatusNone, UserStatusAway, UserStatusBusy};
 use std;
-use std::num::{FromPrimitive};
+use std::fmt;
+use std::cmp::{min};
+use std::vec::{MoveItems};
+use std::io::fs::{readdir};
+use text::{lowercase, edit_distance};
 
-#[repr(uint)]
-#[deriving(FromPrimitive)]
-pub enum _Command {
-    _Quit,
-    _Add,
-    _Del,
+/// What an argument of a command is.
+enum Arg {
+    AddressArg,
+    FriendArg,
+    GroupArg,
+    RequestArg,
+    TransferArg,
+    PathArg,
+    CommandArg,
+    WordArg,
+    /// The rest of the line.
+    TextArg,
 }
 
-impl _Command {
-    fn first() -> _Command {
-        FromPrimitive::from_uint(0).unwrap()
+/// A command that can be typed in command mode.
+pub struct Spec {
+    pub name: &'static str,
+    pub aliases: &'static [&'static str],
+    /// The names and kinds of the arguments.
+    args: &'static [(&'static str, Arg)],
+    /// Number of arguments that must be given. The others are optional.
+    required: uint,
+    /// The first line is a summary that's shown in the list of commands.
+    pub help: &'static str,
+    /// Builds the command from the arguments. They've been checked against `args`
+    /// already.
+    parse: fn(&mut Args) -> ParseResult,
+}
+
+static COMMANDS: [Spec, ..31] = [
+    Spec {
+        name: "q",
+        aliases: &["quit"],
+        args: &[],
+        required: 0,
+        help: "Quit.",
+        parse: parse_quit,
+    },
+    Spec {
+        name: "add",
+        aliases: &[],
+        args: &[("address", AddressArg), ("message", TextArg)],
+        required: 2,
+        help: "Send a friend request.\n\
+               The message is shown to the other side together with the request.",
+        parse: parse_add,
+    },
+    Spec {
+        name: "del",
+        aliases: &[],
+        args: &[("friend", FriendArg)],
+        required: 1,
+        help: "Delete a friend.\n\
+               You're asked for confirmation first. For a few seconds after the \
+               friend is deleted, `:undo` adds them again.",
+        parse: parse_del,
+    },
+    Spec {
+        name: "undo",
+        aliases: &[],
+        args: &[],
+        required: 0,
+        help: "Add the friend we just deleted again.",
+        parse: parse_undo,
+    },
+    Spec {
+        name: "msg",
+        aliases: &[],
+        args: &[("friend", FriendArg), ("message", TextArg)],
+        required: 2,
+        help: "Send a message to a friend.\n\
+               Quotes and escapes are removed from the rest of the line like from \
+               other arguments. The spaces between the words are kept.",
+        parse: parse_msg,
+    },
+    Spec {
+        name: "alias",
+        aliases: &[],
+        args: &[("friend", FriendArg), ("alias", TextArg)],
+        required: 1,
+        help: "Set the name we show for a friend.\n\
+               Aliases can be used instead of ids in other commands, so they can't be \
+               numbers. Without an alias the friend's alias is removed.",
+        parse: parse_alias,
+    },
+    Spec {
+        name: "note",
+        aliases: &[],
+        args: &[("friend", FriendArg), ("note", TextArg)],
+        required: 1,
+        help: "Write down a note about a friend.\n\
+               The note is shown when we chat with them. Without a note the friend's \
+               note is removed.",
+        parse: parse_note,
+    },
+    Spec {
+        name: "mute",
+        aliases: &[],
+        args: &[("friend", FriendArg)],
+        required: 1,
+        help: "Don't notify us about a friend's messages.\n\
+               How we're notified is set in the `[notify]` section of the \
+               configuration file. While our status is busy, we're not notified at \
+               all.",
+        parse: parse_mute,
+    },
+    Spec {
+        name: "unmute",
+        aliases: &[],
+        args: &[("friend", FriendArg)],
+        required: 1,
+        help: "Notify us about a friend's messages again.",
+        parse: parse_unmute,
+    },
+    Spec {
+        name: "send",
+        aliases: &[],
+        args: &[("path", PathArg)],
+        required: 1,
+        help: "Send a file.\n\
+               The file is sent to the friend we're chatting with or the selected \
+               friend.",
+        parse: parse_send,
+    },
+    Spec {
+        name: "transfers",
+        aliases: &[],
+        args: &[],
+        required: 0,
+        help: "Show the file transfers.\n\
+               In the list, `a` accepts the selected file, `p` pauses or resumes the \
+               transfer and `c` cancels it.",
+        parse: parse_transfers,
+    },
+    Spec {
+        name: "file",
+        aliases: &[],
+        args: &[("accept|pause|resume|cancel", WordArg), ("transfer", TransferArg)],
+        required: 2,
+        help: "Control a file transfer.\n\
+               Transfers are given by the number shown in `:transfers`.",
+        parse: parse_file,
+    },
+    Spec {
+        name: "call",
+        aliases: &[],
+        args: &[("friend", FriendArg)],
+        required: 0,
+        help: "Call a friend.\n\
+               Without a friend, the friend we're chatting with or the selected friend \
+               is called. Where the audio comes from and goes to is set with the \
+               `call-input` and `call-output` options.",
+        parse: parse_call,
+    },
+    Spec {
+        name: "answer",
+        aliases: &[],
+        args: &[],
+        required: 0,
+        help: "Answer the call of a friend who's calling us.\n\
+               The terminal also asks whether to answer. No audio is sent before the \
+               call is answered.",
+        parse: parse_answer,
+    },
+    Spec {
+        name: "hangup",
+        aliases: &["hup"],
+        args: &[],
+        required: 0,
+        help: "End the current call, stop calling or decline an incoming call.",
+        parse: parse_hangup,
+    },
+    Spec {
+        name: "accept",
+        aliases: &[],
+        args: &[("request", RequestArg)],
+        required: 1,
+        help: "Accept a friend request.\n\
+               Requests are numbered in the order they're shown in the list.",
+        parse: parse_accept,
+    },
+    Spec {
+        name: "decline",
+        aliases: &[],
+        args: &[("request", RequestArg)],
+        required: 1,
+        help: "Decline a friend request.",
+        parse: parse_decline,
+    },
+    Spec {
+        name: "group",
+        aliases: &[],
+        args: &[],
+        required: 0,
+        help: "Create a group chat.",
+        parse: parse_group,
+    },
+    Spec {
+        name: "invite",
+        aliases: &[],
+        args: &[("friend", FriendArg), ("group", GroupArg)],
+        required: 2,
+        help: "Invite a friend to a group chat.",
+        parse: parse_invite,
+    },
+    Spec {
+        name: "join",
+        aliases: &[],
+        args: &[("friend", FriendArg)],
+        required: 1,
+        help: "Join the group chat a friend invited us to.",
+        parse: parse_join,
+    },
+    Spec {
+        name: "leave",
+        aliases: &[],
+        args: &[("group", GroupArg)],
+        required: 1,
+        help: "Leave a group chat.",
+        parse: parse_leave,
+    },
+    Spec {
+        name: "gmsg",
+        aliases: &[],
+        args: &[("group", GroupArg), ("message", TextArg)],
+        required: 2,
+        help: "Send a message to a group chat.",
+        parse: parse_group_msg,
+    },
+    Spec {
+        name: "myid",
+        aliases: &[],
+        args: &[("clipboard|file", WordArg), ("path", PathArg)],
+        required: 0,
+        help: "Show our address.\n\
+               `:myid clipboard` copies it to the clipboard and `:myid file <path>` \
+               writes it to a file.",
+        parse: parse_my_id,
+    },
+    Spec {
+        name: "nick",
+        aliases: &["name"],
+        args: &[("name", TextArg)],
+        required: 1,
+        help: "Set our name.",
+        parse: parse_nick,
+    },
+    Spec {
+        name: "status",
+        aliases: &[],
+        args: &[("message", TextArg)],
+        required: 0,
+        help: "Set our status message.\n\
+               Without a message the status message is cleared.",
+        parse: parse_status,
+    },
+    Spec {
+        name: "online",
+        aliases: &[],
+        args: &[],
+        required: 0,
+        help: "Show us as online.",
+        parse: parse_online,
+    },
+    Spec {
+        name: "away",
+        aliases: &[],
+        args: &[],
+        required: 0,
+        help: "Show us as away.",
+        parse: parse_away,
+    },
+    Spec {
+        name: "busy",
+        aliases: &[],
+        args: &[],
+        required: 0,
+        help: "Show us as busy.",
+        parse: parse_busy,
+    },
+    Spec {
+        name: "source",
+        aliases: &[],
+        args: &[("path", PathArg)],
+        required: 0,
+        help: "Reload the configuration file.\n\
+               If a path is given, the configuration is read from there instead.",
+        parse: parse_source,
+    },
+    Spec {
+        name: "messages",
+        aliases: &[],
+        args: &[],
+        required: 0,
+        help: "Show the messages that were shown in the status line.",
+        parse: parse_messages,
+    },
+    Spec {
+        name: "help",
+        aliases: &["h"],
+        args: &[("command", CommandArg)],
+        required: 0,
+        help: "Show the list of commands or the help of a command.",
+        parse: parse_help,
+    },
+];
+
+impl Spec {
+    /// Returns what the `n`th argument of the command is.
+    fn arg(&self, n: uint) -> Option<Arg> {
+        match self.args.last() {
+            Some(&(_, TextArg)) if n >= self.args.len() => Some(TextArg),
+            _ if n < self.args.len() => Some(self.args[n].val1()),
+            _ => None,
+        }
+    }
+
+    pub fn usage(&self) -> String {
+        let mut usage = format!(":{}", self.name);
+        for (i, &(name, _)) in self.args.iter().enumerate() {
+            if i < self.required {
+                usage.push_str(format!(" <{}>", name).as_slice());
+            } else {
+                usage.push_str(format!(" [{}]", name).as_slice());
+            }
+        }
+        usage
+    }
+
+    fn summary(&self) -> &'static str {
+        self.help.lines().next().unwrap_or("")
+    }
+}
+
+/// Returns the command called `name`.
+pub fn find(name: &str) -> Option<&'static Spec> {
+    COMMANDS.iter().find(|c| c.name == name || c.aliases.iter().any(|&a| a == name))
+}
+
+/// Returns the commands whose names are similar to `name`.
+fn similar(name: &str) -> Vec<&'static str> {
+    let mut names = Vec::new();
+    for c in COMMANDS.iter() {
+        let d = c.aliases.iter().fold(edit_distance(name, c.name), |d, &a| {
+            min(d, edit_distance(name, a))
+        });
+        if d <= 2 && d < name.len() {
+            names.push((d, c.name));
+        }
     }
+    names.sort();
+    names.move_iter().map(|(_, n)| n).collect()
+}
 
-    fn next(self) -> Option<_Command> {
-        FromPrimitive::from_uint(self as uint + 1)
+fn unknown_command(name: &str) -> String {
+    let similar = similar(name);
+    if similar.len() == 0 {
+        return format!("unknown command `{}`", name);
+    }
+    let names: Vec<String> = similar.iter().map(|n| format!("`{}`", n)).collect();
+    format!("unknown command `{}`, did you mean {}?", name, names.connect(" or "))
+}
+
+/// Returns the text shown by `:help`.
+pub fn help(topic: Option<&str>) -> std::result::Result<Vec<String>, String> {
+    let mut lines = Vec::new();
+    match topic {
+        None => {
+            let usages: Vec<String> = COMMANDS.iter().map(|c| c.usage()).collect();
+            let width = usages.iter().map(|u| u.len()).max().unwrap_or(0);
+            for (c, usage) in COMMANDS.iter().zip(usages.iter()) {
+                let pad = " ".repeat(width - usage.len());
+                lines.push(format!("{}{}   {}", usage, pad, c.summary()));
+            }
+            lines.push(String::new());
+            lines.push("Type :help <command> for more about a command.".to_string());
+        },
+        Some(name) => {
+            let c = match find(name) {
+                Some(c) => c,
+                None => return Err(unknown_command(name)),
+            };
+            lines.push(c.usage());
+            if c.aliases.len() > 0 {
+                lines.push(format!("Aliases: {}", c.aliases.connect(", ")));
+            }
+            lines.push(String::new());
+            for line in c.help.lines() {
+                lines.push(line.to_string());
+            }
+        },
     }
+    Ok(lines)
+}
+
+fn parse_quit(_: &mut Args) -> ParseResult {
+    Ok(Quit)
+}
+
+fn parse_add(args: &mut Args) -> ParseResult {
+    let address = args.address();
+    Ok(Add(address, args.string()))
+}
+
+fn parse_del(args: &mut Args) -> ParseResult {
+    Ok(Del(args.id()))
+}
+
+fn parse_undo(_: &mut Args) -> ParseResult {
+    Ok(Undo)
+}
+
+fn parse_msg(args: &mut Args) -> ParseResult {
+    let id = args.id();
+    Ok(Msg(id, args.string()))
+}
+
+fn parse_alias(args: &mut Args) -> ParseResult {
+    let id = args.id();
+    let alias = args.opt_string().unwrap_or(String::new());
+    // It would be taken for an id.
+    if from_str::<i32>(alias.as_slice()).is_some() {
+        return Err("alias mustn't be a number");
+    }
+    Ok(Alias(id, alias))
+}
+
+fn parse_note(args: &mut Args) -> ParseResult {
+    let id = args.id();
+    Ok(Note(id, args.opt_string().unwrap_or(String::new())))
+}
+
+fn parse_mute(args: &mut Args) -> ParseResult {
+    Ok(Mute(args.id(), true))
+}
+
+fn parse_unmute(args: &mut Args) -> ParseResult {
+    Ok(Mute(args.id(), false))
+}
+
+fn parse_send(args: &mut Args) -> ParseResult {
+    Ok(SendFile(Path::new(args.string())))
+}
+
+fn parse_transfers(_: &mut Args) -> ParseResult {
+    Ok(ShowTransfers)
+}
+
+fn parse_file(args: &mut Args) -> ParseResult {
+    let action = args.string();
+    let action = match action.as_slice() {
+        "accept" => FileAccept,
+        "pause" => FilePause,
+        "resume" => FileResume,
+        "cancel" | "reject" => FileCancel,
+        _ => return Err("expected `accept`, `pause`, `resume` or `cancel`"),
+    };
+    Ok(File(action, args.number()))
+}
+
+fn parse_call(args: &mut Args) -> ParseResult {
+    Ok(Call(args.opt_id()))
+}
+
+fn parse_answer(_: &mut Args) -> ParseResult {
+    Ok(AnswerCall)
+}
+
+fn parse_hangup(_: &mut Args) -> ParseResult {
+    Ok(EndCall)
+}
+
+fn parse_accept(args: &mut Args) -> ParseResult {
+    Ok(Accept(args.number()))
+}
+
+fn parse_decline(args: &mut Args) -> ParseResult {
+    Ok(Decline(args.number()))
+}
+
+fn parse_group(_: &mut Args) -> ParseResult {
+    Ok(GroupCreate)
+}
+
+fn parse_invite(args: &mut Args) -> ParseResult {
+    let friend = args.id();
+    Ok(Invite(friend, args.id()))
+}
+
+fn parse_join(args: &mut Args) -> ParseResult {
+    Ok(Join(args.id()))
+}
+
+fn parse_leave(args: &mut Args) -> ParseResult {
+    Ok(Leave(args.id()))
+}
+
+fn parse_group_msg(args: &mut Args) -> ParseResult {
+    let group = args.id();
+    Ok(GroupMsg(group, args.string()))
+}
+
+fn parse_my_id(args: &mut Args) -> ParseResult {
+    let output = match args.opt_string() {
+        None => ToStatus,
+        Some(ref s) if s.as_slice() == "clipboard" => {
+            if args.opt_string().is_some() {
+                return Err("too many arguments");
+            }
+            ToClipboard
+        },
+        Some(ref s) if s.as_slice() == "file" => match args.opt_string() {
+            Some(path) => ToFile(Path::new(path)),
+            None => return Err("missing path"),
+        },
+        Some(_) => return Err("expected `file` or `clipboard`"),
+    };
+    Ok(MyId(output))
+}
+
+fn parse_nick(args: &mut Args) -> ParseResult {
+    Ok(SetName(args.string()))
+}
 
-    fn text(self) -> &'static str {
-        match self {
-            _Quit => "q",
-            _Add => "add",
-            _Del => "del",
+fn parse_status(args: &mut Args) -> ParseResult {
+    // An empty status clears the status message.
+    Ok(SetStatusMessage(args.opt_string().unwrap_or(String::new())))
+}
+
+fn parse_online(_: &mut Args) -> ParseResult {
+    Ok(SetUserStatus(UserStatusNone))
+}
+
+fn parse_away(_: &mut Args) -> ParseResult {
+    Ok(SetUserStatus(UserStatusAway))
+}
+
+fn parse_busy(_: &mut Args) -> ParseResult {
+    Ok(SetUserStatus(UserStatusBusy))
+}
+
+fn parse_source(args: &mut Args) -> ParseResult {
+    Ok(Source(args.opt_string().map(|s| Path::new(s))))
+}
+
+fn parse_messages(_: &mut Args) -> ParseResult {
+    Ok(Messages)
+}
+
+fn parse_help(args: &mut Args) -> ParseResult {
+    Ok(Help(args.opt_string()))
+}
+
+pub type Result = std::result::Result<Command, String>;
+
+type ParseResult = std::result::Result<Command, &'static str>;
+
+/// An argument that has been checked against the `Spec` of its command.
+enum Value {
+    AddressVal(Address),
+    /// A friend or a group.
+    IdVal(i32),
+    /// A request or a transfer.
+    NumberVal(uint),
+    StrVal(String),
+}
+
+/// The checked arguments of a command in the order of `Spec.args`. Asking for an
+/// argument of another kind than the `Spec` says is a bug.
+struct Args {
+    values: MoveItems<Value>,
+}
+
+impl Args {
+    fn address(&mut self) -> Address {
+        match self.values.next() {
+            Some(AddressVal(addr)) => addr,
+            _ => fail!("expected an address"),
         }
     }
 
-    fn parse(self, iter: TokenIter) -> Result {
-        match self {
-            _Quit => Ok(Quit),
-            _Add => self.parse_add(iter),
-            _Del => self.parse_del(iter),
+    fn id(&mut self) -> i32 {
+        self.opt_id().expect("expected an id")
+    }
+
+    fn opt_id(&mut self) -> Option<i32> {
+        match self.values.next() {
+            Some(IdVal(id)) => Some(id),
+            None => None,
+            _ => fail!("expected an id"),
         }
     }
 
-    fn parse_add(self, mut iter: TokenIter) -> Result {
-        let address = match iter.next() {
-            Some(s) => match from_str(s) {
-                Some(addr) => addr,
-                _ => return Err("invalid address"),
-            },
-            None => return Err("missing address"),
-        };
-        let msg = match iter.next() {
-            Some(s) if s.len() > 0 => s.to_string(),
-            Some(_) => return Err("message musn't be empty"),
-            _ => return Err("missing message"),
-        };
-        if iter.next().is_some() {
-            return Err("too many arguments");
+    fn number(&mut self) -> uint {
+        match self.values.next() {
+            Some(NumberVal(n)) => n,
+            _ => fail!("expected a number"),
         }
-        Ok(Add(address, msg))
     }
 
-    fn parse_del(self, mut iter: TokenIter) -> Result {
-        let s = match iter.next() {
-            Some(s) => s,
-            None => return Err("missing id"),
-        };
-        let id = match from_str(s) {
-            Some(i) => i,
-            None => return Err("invalid id"),
-        };
-        if iter.next().is_some() {
-            return Err("too many arguments");
+    fn string(&mut self) -> String {
+        self.opt_string().expect("expected a string")
+    }
+
+    fn opt_string(&mut self) -> Option<String> {
+        match self.values.next() {
+            Some(StrVal(s)) => Some(s),
+            None => None,
+            _ => fail!("expected a string"),
         }
-        Ok(Del(id))
     }
 }
 
-pub type Result = std::result::Result<Command, &'static str>;
+pub enum FileAction {
+    FileAccept,
+    FilePause,
+    FileResume,
+    FileCancel,
+}
+
+/// Where to put our address.
+pub enum IdOutput {
+    ToStatus,
+    ToFile(Path),
+    ToClipboard,
+}
 
 pub enum Command {
     Quit,
     Add(Address, String),
     Del(i32),
+    Undo,
+    Msg(i32, String),
+    /// Set the alias of a friend. An empty alias removes it.
+    Alias(i32, String),
+    /// Set the note about a friend. An empty note removes it.
+    Note(i32, String),
+    /// Mute or unmute a friend.
+    Mute(i32, bool),
+    Accept(uint),
+    Decline(uint),
+    GroupCreate,
+    Invite(i32, i32),
+    Join(i32),
+    Leave(i32),
+    GroupMsg(i32, String),
+    MyId(IdOutput),
+    SetName(String),
+    SetStatusMessage(String),
+    SetUserStatus(UserStatus),
+    /// Reload the configuration file, optionally from another path.
+    Source(Option<Path>),
+    /// Show the help, optionally of a single command.
+    Help(Option<String>),
+    /// Show the message log.
+    Messages,
+    /// Offer a file to the current friend.
+    SendFile(Path),
+    ShowTransfers,
+    File(FileAction, uint),
+    /// Call a friend or, without a friend, the current one.
+    Call(Option<i32>),
+    AnswerCall,
+    EndCall,
 }
 
-pub fn parse(s: &str) -> Result {
-    let mut iter = s.tokens();
-    let first = match iter.next() {
-        Some(s) => s,
-        None => return Err("empty command"),
+pub struct Candidate {
+    /// The text that replaces the word.
+    pub text: String,
+    /// The text shown in the list of candidates.
+    pub display: String,
+}
+
+/// What we know about the world when completing arguments.
+pub struct CompletionData {
+    pub friends: Vec<(i32, String)>,
+    pub groups: Vec<(i32, String)>,
+    pub addresses: Vec<String>,
+}
+
+/// Completes the argument under the cursor in `line`.
+///
+/// Only the part of the argument before the cursor is completed. Returns where the
+/// argument starts and ends in `line` and the candidates that can replace it. The
+/// text of the candidates is escaped so that it can be put into the line as it is.
+pub fn complete(line: &str, cursor: uint,
+                data: &CompletionData) -> (uint, uint, Vec<Candidate>) {
+    let before = match tokenize_partial(line.slice_to(cursor)) {
+        Ok(t) => t,
+        Err(_) => return (cursor, cursor, Vec::new()),
     };
-    let mut command = _Command::first();
-    loop {
-        if first == command.text() {
-            return command.parse(iter);
+    // The cursor is either at the end of an argument or between two of them.
+    let (start, word, args) = match before.last() {
+        Some(t) if t.end == cursor => (t.start, t.text.as_slice(), before.len() - 1),
+        _ => (cursor, "", before.len()),
+    };
+    let end = match tokenize_partial(line) {
+        Ok(t) => t.iter().find(|t| t.start == start).map(|t| t.end).unwrap_or(cursor),
+        Err(_) => cursor,
+    };
+    let candidates = complete_arg(before.as_slice(), args, word, data);
+    let candidates = candidates.move_iter().map(|c| {
+        Candidate { text: escape(c.text.as_slice()), display: c.display }
+    }).collect();
+    (start, end, candidates)
+}
+
+/// Completes `word`, the argument number `arg` on the line. `tokens` are the
+/// arguments before the cursor.
+fn complete_arg(tokens: &[Token], arg: uint, word: &str,
+                data: &CompletionData) -> Vec<Candidate> {
+    if arg == 0 {
+        return complete_command(word);
+    }
+    let command = match find(tokens[0].text.as_slice()) {
+        Some(c) => c,
+        None => return Vec::new(),
+    };
+    match command.arg(arg - 1) {
+        Some(AddressArg) => {
+            data.addresses.iter().filter(|a| a.as_slice().starts_with(word)).map(|a| {
+                Candidate { text: a.clone(), display: a.clone() }
+            }).collect()
+        },
+        Some(FriendArg) => complete_id(word, data.friends.as_slice()),
+        Some(GroupArg) => complete_id(word, data.groups.as_slice()),
+        Some(PathArg) => complete_path(word),
+        Some(CommandArg) => complete_command(word),
+        _ => Vec::new(),
+    }
+}
+
+/// Escapes the characters in `s` that `tokenize` treats specially.
+fn escape(s: &str) -> String {
+    let mut escaped = String::new();
+    for c in s.chars() {
+        match c {
+            ' ' | '\t' | '\\' | '\'' | '"' => escaped.push_char('\\'),
+            _ => { },
         }
-        command = match command.next() {
-            Some(c) => c,
+        escaped.push_char(c);
+    }
+    escaped
+}
+
+fn complete_command(word: &str) -> Vec<Candidate> {
+    COMMANDS.iter().filter(|c| c.name.starts_with(word)).map(|c| {
+        Candidate { text: c.name.to_string(), display: c.name.to_string() }
+    }).collect()
+}
+
+/// Completes ids that start with `word` or whose name starts with `word`.
+fn complete_id(word: &str, ids: &[(i32, String)]) -> Vec<Candidate> {
+    let word = lowercase(word);
+    let mut candidates = Vec::new();
+    for &(id, ref name) in ids.iter() {
+        let text = id.to_string();
+        if text.as_slice().starts_with(word.as_slice())
+                || lowercase(name.as_slice()).as_slice().starts_with(word.as_slice()) {
+            candidates.push(Candidate {
+                display: format!("{} {}", text, name),
+                text: text,
+            });
+        }
+    }
+    candidates
+}
+
+fn complete_path(word: &str) -> Vec<Candidate> {
+    let (dir, file) = match word.rfind('/') {
+        Some(pos) => (word.slice_to(pos + 1), word.slice_from(pos + 1)),
+        None => ("", word),
+    };
+    let entries = match readdir(&Path::new(if dir.len() == 0 { "." } else { dir })) {
+        Ok(e) => e,
+        Err(_) => return Vec::new(),
+    };
+    let mut candidates = Vec::new();
+    for path in entries.iter() {
+        let name = match path.filename_str() {
+            Some(n) => n,
+            None => continue,
+        };
+        // Hidden files only if asked for
+        if !name.starts_with(file) || (name.starts_with(".") && !file.starts_with(".")) {
+            continue;
+        }
+        let suffix = if path.is_dir() { "/" } else { "" };
+        candidates.push(Candidate {
+            text: format!("{}{}{}", dir, name, suffix),
+            display: format!("{}{}", name, suffix),
+        });
+    }
+    candidates.sort_by(|a, b| a.text.cmp(&b.text));
+    candidates
+}
+
+/// Parses the command line `s`.
+///
+/// `aliases` maps the aliases of friends to their ids.
+pub fn parse(s: &str, aliases: &[(i32, String)]) -> Result {
+    let tokens = match tokenize(s) {
+        Ok(t) => t,
+        Err(e) => return Err(e.to_string()),
+    };
+    let mut iter = TokenIter {
+        line: s,
+        tokens: tokens.as_slice(),
+        pos: 0,
+    };
+    let name = match iter.next() {
+        Some(s) => s,
+        None => return Err("empty command".to_string()),
+    };
+    let command = match find(name) {
+        Some(c) => c,
+        None => return Err(unknown_command(name)),
+    };
+    let res = match check_args(command, &mut iter, aliases) {
+        Ok(values) => {
+            let mut args = Args { values: values.move_iter() };
+            (command.parse)(&mut args).map_err(|e| e.to_string())
+        },
+        Err(e) => Err(e),
+    };
+    res.map_err(|e| format!("{} (usage: {})", e, command.usage()))
+}
+
+/// Checks the arguments in `iter` against the ones `spec` expects and converts them.
+fn check_args(spec: &Spec, iter: &mut TokenIter,
+              aliases: &[(i32, String)]) -> std::result::Result<Vec<Value>, String> {
+    let mut values = Vec::new();
+    for (i, &(name, kind)) in spec.args.iter().enumerate() {
+        let required = i < spec.required;
+        let s = match kind {
+            TextArg => iter.rest(),
+            _ => iter.next().map(|s| s.to_string()),
+        };
+        let s = match s {
+            Some(s) => s,
+            None if required => return Err(format!("missing {}", name)),
             None => break,
         };
+        let value = match kind {
+            AddressArg => match from_str(s.as_slice()) {
+                Some(addr) => AddressVal(addr),
+                None => return Err("invalid address".to_string()),
+            },
+            FriendArg => IdVal(try!(friend(s.as_slice(), aliases))),
+            GroupArg => match from_str(s.as_slice()) {
+                Some(id) => IdVal(id),
+                None => return Err(format!("invalid {}", name)),
+            },
+            RequestArg | TransferArg => match from_str(s.as_slice()) {
+                Some(n) => NumberVal(n),
+                None => return Err(format!("invalid {}", name)),
+            },
+            PathArg if s.len() == 0 => return Err(format!("missing {}", name)),
+            TextArg if s.len() == 0 && required => {
+                return Err(format!("{} mustn't be empty", name));
+            },
+            PathArg | CommandArg | WordArg | TextArg => StrVal(s),
+        };
+        values.push(value);
+    }
+    if !iter.at_end() {
+        return Err("too many arguments".to_string());
     }
-    Err("unknown command")
+    Ok(values)
 }
 
-trait Tokens {
-    fn tokens<'a>(&'a self) -> TokenIter<'a>;
+/// Returns the id of the friend `s`. Friends can be given by id or alias.
+fn friend(s: &str, aliases: &[(i32, String)]) -> std::result::Result<i32, String> {
+    match from_str(s) {
+        Some(id) => return Ok(id),
+        None => { },
+    }
+    let lower = lowercase(s);
+    let ids: Vec<i32> = aliases.iter().filter(|&&(_, ref a)| {
+        lowercase(a.as_slice()) == lower
+    }).map(|&(id, _)| id).collect();
+    match ids.len() {
+        0 => Err("unknown friend".to_string()),
+        1 => Ok(*ids.get(0)),
+        _ => Err(format!("`{}` is the alias of more than one friend", s)),
+    }
+}
+
+/// An argument on the command line.
+struct Token {
+    /// The argument with quotes and escapes removed.
+    text: String,
+    /// Where the argument starts and ends in the line.
+    start: uint,
+    end: uint,
+}
+
+pub struct TokenError {
+    /// Byte offset of the offending character.
+    pub pos: uint,
+    pub msg: &'static str,
+}
+
+impl fmt::Show for TokenError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} at column {}", self.msg, self.pos + 1)
+    }
 }
 
-impl<'a> Tokens for &'a str {
-    fn tokens<'a>(&'a self) -> TokenIter<'a> {
-        TokenIter {
-            st: *self,
-            cur: 0,
+static UNTERMINATED: &'static str = "unterminated quote";
+
+/// Splits `s` into arguments.
+///
+/// Arguments are separated by whitespace. Text in single quotes is taken literally.
+/// In double quotes and outside of quotes, a backslash escapes the next character.
+fn tokenize(s: &str) -> std::result::Result<Vec<Token>, TokenError> {
+    split(s, false)
+}
+
+/// Like `tokenize` but accepts a line that's still being typed, i.e. one that ends
+/// in a quote or after a backslash.
+fn tokenize_partial(s: &str) -> std::result::Result<Vec<Token>, TokenError> {
+    split(s, true)
+}
+
+fn split(s: &str, partial: bool) -> std::result::Result<Vec<Token>, TokenError> {
+    let mut tokens = Vec::new();
+    let mut chars = s.char_indices().peekable();
+    loop {
+        while chars.peek().map(|&(_, c)| c == ' ' || c == '\t').unwrap_or(false) {
+            chars.next();
+        }
+        let start = match chars.peek() {
+            Some(&(i, _)) => i,
+            None => break,
+        };
+        let mut text = String::new();
+        let mut end = s.len();
+        loop {
+            let (i, c) = match chars.next() {
+                Some(x) => x,
+                None => break,
+            };
+            match c {
+                ' ' | '\t' => {
+                    end = i;
+                    break;
+                },
+                '\\' => match chars.next() {
+                    Some((_, c)) => text.push_char(c),
+                    None if partial => break,
+                    None => return Err(TokenError { pos: i, msg: "trailing backslash" }),
+                },
+                '\'' => loop {
+                    match chars.next() {
+                        Some((_, '\'')) => break,
+                        Some((_, c)) => text.push_char(c),
+                        None if partial => break,
+                        None => return Err(TokenError { pos: i, msg: UNTERMINATED }),
+                    }
+                },
+                '"' => loop {
+                    let c = match chars.next() {
+                        Some((_, '"')) => break,
+                        Some((_, '\\')) => chars.next().map(|(_, c)| c),
+                        Some((_, c)) => Some(c),
+                        None => None,
+                    };
+                    match c {
+                        Some(c) => text.push_char(c),
+                        None if partial => break,
+                        None => return Err(TokenError { pos: i, msg: UNTERMINATED }),
+                    }
+                },
+                c => text.push_char(c),
+            }
         }
+        tokens.push(Token { text: text, start: start, end: end });
     }
+    Ok(tokens)
 }
 
 struct TokenIter<'a> {
-    st: &'a str,
-    cur: uint,
+    line: &'a str,
+    tokens: &'a [Token],
+    pos: uint,
+}
+
+impl<'a> TokenIter<'a> {
+    fn at_end(&self) -> bool {
+        self.pos >= self.tokens.len()
+    }
+
+    /// Returns the rest of the line as a single argument.
+    ///
+    /// Quotes and escapes are removed from the arguments but the whitespace between
+    /// them is kept as typed.
+    fn rest(&mut self) -> Option<String> {
+        if self.pos >= self.tokens.len() {
+            return None;
+        }
+        let mut rest = self.tokens[self.pos].text.clone();
+        for i in range(self.pos + 1, self.tokens.len()) {
+            let space = self.line.slice(self.tokens[i - 1].end, self.tokens[i].start);
+            rest.push_str(space);
+            rest.push_str(self.tokens[i].text.as_slice());
+        }
+        self.pos = self.tokens.len();
+        Some(rest)
+    }
 }
 
 impl<'a> Iterator<&'a str> for TokenIter<'a> {
     fn next(&mut self) -> Option<&'a str> {
-        if self.cur == self.st.len() {
+        if self.pos >= self.tokens.len() {
             return None;
         }
-        let bytes = self.st.as_bytes();
-        while self.cur < self.st.len() && bytes[self.cur] == ' ' as u8 {
-            self.cur += 1
-        };
-        let start = self.cur;
-        let mut quoted = false;
-        let mut escaped = false;
-        while self.cur < self.st.len() {
-            if !escaped && bytes[self.cur] == '"' as u8 {
-                quoted = !quoted;
-            }
-            escaped = !escaped && bytes[self.cur] == '\\' as u8;
-            if !quoted && bytes[self.cur] == ' ' as u8 {
-                break;
-            }
-            self.cur += 1
+        self.pos += 1;
+        Some(self.tokens[self.pos - 1].text.as_slice())
+    }
+}
+
+#[cfg(test)]
+fn words(s: &str) -> Vec<String> {
+    match tokenize(s) {
+        Ok(t) => t.move_iter().map(|t| t.text).collect(),
+        Err(e) => fail!("{}", e),
+    }
+}
+
+#[test]
+fn test_tokenize() {
+    assert_eq!(words(""), vec!());
+    assert_eq!(words("  add  x\ty "),
+               vec!("add".to_string(), "x".to_string(), "y".to_string()));
+    assert_eq!(words("a\\ b c"), vec!("a b".to_string(), "c".to_string()));
+    assert_eq!(words("\"hello world\""), vec!("hello world".to_string()));
+    assert_eq!(words("'a \\\" b'"), vec!("a \\\" b".to_string()));
+    assert_eq!(words("\"a \\\" b\""), vec!("a \" b".to_string()));
+    assert_eq!(words("x\"y z\"'w'"), vec!("xy zw".to_string()));
+    assert_eq!(words("'' \"\""), vec!("".to_string(), "".to_string()));
+    assert_eq!(words("\"über\" ö"), vec!("über".to_string(), "ö".to_string()));
+}
+
+#[test]
+fn test_tokenize_errors() {
+    let err = |s: &str| match tokenize(s) {
+        Ok(_) => fail!("`{}` should not parse", s),
+        Err(e) => (e.pos, e.msg),
+    };
+    assert_eq!(err("msg 1 \"hi"), (6, "unterminated quote"));
+    assert_eq!(err("msg 'hi"), (4, "unterminated quote"));
+    assert_eq!(err("msg \"hi\\\""), (4, "unterminated quote"));
+    assert_eq!(err("msg hi\\"), (6, "trailing backslash"));
+}
+
+#[test]
+fn test_rest() {
+    let rest = |s: &str| {
+        let tokens = tokenize(s).ok().unwrap();
+        let mut iter = TokenIter {
+            line: s,
+            tokens: tokens.as_slice(),
+            pos: 0,
         };
-        Some(self.st.slice(start, self.cur))
+        iter.next();
+        iter.rest()
+    };
+    assert_eq!(rest("msg"), None);
+    assert_eq!(rest("msg  \"hello world\"  "), Some("hello world".to_string()));
+    assert_eq!(rest("msg  \"hi\"  there  "), Some("hi  there".to_string()));
+    assert_eq!(rest("msg don\\'t go"), Some("don't go".to_string()));
+}
+
+#[test]
+fn test_complete() {
+    let data = CompletionData {
+        friends: vec!((7, "Arya".to_string()), (8, "Arya Stark".to_string())),
+        groups: vec!(),
+        addresses: vec!(),
+    };
+    let texts = |line: &str, cursor: uint| {
+        let (start, end, candidates) = complete(line, cursor, &data);
+        (start, end, candidates.move_iter().map(|c| c.text).collect::<Vec<String>>())
+    };
+    assert_eq!(texts("ms", 2), (0, 2, vec!("msg".to_string())));
+    assert_eq!(texts("msg ar hello", 6), (4, 6, vec!("7".to_string(), "8".to_string())));
+    assert_eq!(texts("msg 'arya s' hi", 11), (4, 12, vec!("8".to_string())));
+    assert_eq!(texts("help \"ms", 8), (5, 8, vec!("msg".to_string())));
+    assert_eq!(texts("del  1", 4), (4, 4, vec!("7".to_string(), "8".to_string())));
+}
+
+#[test]
+fn test_parse() {
+    match parse("msg 3 hello  world", []) {
+        Ok(Msg(3, ref m)) if m.as_slice() == "hello  world" => { },
+        _ => fail!(),
+    }
+    match parse("nick 'Jon Snow'", []) {
+        Ok(SetName(ref n)) if n.as_slice() == "Jon Snow" => { },
+        _ => fail!(),
+    }
+    match parse("status", []) {
+        Ok(SetStatusMessage(ref s)) if s.len() == 0 => { },
+        _ => fail!(),
     }
+    match parse("myid file \"my id\"", []) {
+        Ok(MyId(ToFile(ref p))) if p == &Path::new("my id") => { },
+        _ => fail!(),
+    }
+    match parse("gmsg 1 \"unterminated", []) {
+        Err(ref e) if e.as_slice() == "unterminated quote at column 8" => { },
+        _ => fail!(),
+    }
+    match parse("del 1 2", []) {
+        Err(ref e) if e.as_slice() == "too many arguments (usage: :del <friend>)" => { },
+        _ => fail!(),
+    }
+    match parse("quit", []) {
+        Ok(Quit) => { },
+        _ => fail!(),
+    }
+    let aliases = [(7, "Arya".to_string())];
+    match parse("msg arya 'hi'", aliases.as_slice()) {
+        Ok(Msg(7, ref m)) if m.as_slice() == "hi" => { },
+        _ => fail!(),
+    }
+    match parse("alias 7 42", aliases.as_slice()) {
+        Err(ref e) if e.as_slice().starts_with("alias mustn't be a number") => { },
+        _ => fail!(),
+    }
+    let twins = [(1, "Jaime".to_string()), (2, "jaime".to_string())];
+    match parse("del jaime", twins.as_slice()) {
+        Err(ref e) if e.as_slice().starts_with("`jaime` is the alias of more") => { },
+        _ => fail!(),
+    }
+    match parse("del sansa", aliases.as_slice()) {
+        Err(ref e) if e.as_slice() == "unknown friend (usage: :del <friend>)" => { },
+        _ => fail!(),
+    }
+    match parse("file pause x", []) {
+        Err(ref e) if e.as_slice() == "invalid transfer \
+                                      (usage: :file <accept|pause|resume|cancel> \
+                                      <transfer>)" => { },
+        _ => fail!(),
+    }
+    match parse("msg 3", []) {
+        Err(ref e) if e.as_slice() == "missing message \
+                                      (usage: :msg <friend> <message>)" => { },
+        _ => fail!(),
+    }
+    match parse("mgs 1 hi", []) {
+        Err(ref e) if e.as_slice() == "unknown command `mgs`, did you mean `msg`?" => { },
+        _ => fail!(),
+    }
+}
+
+#[test]
+fn test_help() {
+    assert!(help(Some("frobnicate")).is_err());
+    let lines = help(Some("quit")).ok().unwrap();
+    assert_eq!(lines.get(0).as_slice(), ":q");
+    assert_eq!(lines.get(1).as_slice(), "Aliases: quit");
+    let lines = help(None).ok().unwrap();
+    assert_eq!(lines.len(), COMMANDS.len() + 2);
 }
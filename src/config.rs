@@ -0,0 +1,244 @@
+use tox::core::{ClientId};
+use keys::{Keymap};
+use notify::{Notifier};
+use colors;
+use std::os::{getenv};
+use std::io::{File, BufferedReader};
+use std::num::{from_str_radix};
+
+// The public nodes from the wiki
+static PUBLIC_NODES: [(&'static str, u16, &'static str), ..7] = [
+    ("192.254.75.98",   33445, "951C88B7E75C867418ACDB5D273821372BB5BD652740BCDF623A4FA293E75D2F"),
+    ("144.76.60.215",   33445, "04119E835DF3E78BACF0F84235B300546AF8B936F035185E2A8E9E0A67C8924F"),
+    ("23.226.230.47",   33445, "A09162D68618E742FFBCA1C2C70385E6679604B2D80EA6E84AD0996A1AC8A074"),
+    ("37.187.20.216",   33445, "4FD54CFD426A338399767E56FD0F44F5E35FA8C38C8E87C8DC3FEAC0160F8E17"),
+    ("54.199.139.199",  33445, "7F9C31FE850E97CEFD4C4591DF93FC757C7C12549DDD55F8EEAECC34FE76C029"),
+    ("109.169.46.133",  33445, "7F31BFC93B8E4016A902144D0B110C3EA97CB7D43F1C4D21BCAE998A7C838821"),
+    ("192.210.149.121", 33445, "F404ABAA1C99A9D37D61AB54898F56793E1DEF8BD46B1038B9D822E8460FAB67"),
+];
+
+pub struct Node {
+    pub host: String,
+    pub port: u16,
+    pub key: ClientId,
+}
+
+impl Node {
+    /// Parses a node of the form `host port key`.
+    pub fn parse(s: &str) -> Result<Node, &'static str> {
+        let mut words = s.words();
+        let host = match words.next() {
+            Some(h) => h.to_string(),
+            None => return Err("missing host"),
+        };
+        let port = match words.next() {
+            Some(p) => match from_str(p) {
+                Some(p) => p,
+                None => return Err("invalid port"),
+            },
+            None => return Err("missing port"),
+        };
+        let key = match words.next() {
+            Some(k) => match from_str(k) {
+                Some(k) => k,
+                None => return Err("invalid key"),
+            },
+            None => return Err("missing key"),
+        };
+        if words.next().is_some() {
+            return Err("trailing characters");
+        }
+        Ok(Node { host: host, port: port, key: key })
+    }
+}
+
+pub struct Config {
+    /// Nodes we bootstrap from in addition to the public nodes.
+    pub nodes: Vec<Node>,
+    /// Whether to bootstrap from the public nodes.
+    pub public_nodes: bool,
+    /// Colors that override the default palette.
+    pub colors: Vec<(i16, u32)>,
+    pub keymap: Keymap,
+    /// How long to wait for the rest of an escape sequence in milliseconds.
+    pub escdelay: i32,
+    /// Where received files are saved.
+    pub download_dir: Path,
+    /// The file we read the audio we send in calls from. See `calls::FileBackend`.
+    pub call_input: Option<Path>,
+    /// The file we write the audio we receive in calls to.
+    pub call_output: Option<Path>,
+    pub notifier: Notifier,
+}
+
+impl Config {
+    pub fn new() -> Config {
+        Config {
+            nodes: Vec::new(),
+            public_nodes: true,
+            colors: Vec::new(),
+            keymap: Keymap::new(),
+            escdelay: 25,
+            download_dir: default_download_dir(),
+            call_input: None,
+            call_output: None,
+            notifier: Notifier::new(),
+        }
+    }
+
+    /// Returns all nodes we should bootstrap from.
+    pub fn bootstrap_nodes(&self) -> Vec<Node> {
+        let mut nodes = Vec::new();
+        if self.public_nodes {
+            for &(host, port, key) in PUBLIC_NODES.iter() {
+                nodes.push(Node {
+                    host: host.to_string(),
+                    port: port,
+                    key: from_str(key).unwrap(),
+                });
+            }
+        }
+        for node in self.nodes.iter() {
+            nodes.push(Node {
+                host: node.host.clone(),
+                port: node.port,
+                key: node.key.clone(),
+            });
+        }
+        nodes
+    }
+
+    fn set(&mut self, section: &str, key: &str, val: &str) -> Result<(), &'static str> {
+        match (section, key) {
+            ("bootstrap", "public") => self.public_nodes = try!(parse_bool(val)),
+            ("bootstrap", "node") => self.nodes.push(try!(Node::parse(val))),
+            ("colors", name) => {
+                let color = match colors::by_name(name) {
+                    Some(c) => c,
+                    None => return Err("unknown color"),
+                };
+                self.colors.push((color, try!(parse_rgb(val))));
+            },
+            ("keys", key) => try!(self.keymap.bind_normal(key, val)),
+            ("prompt-keys", key) => try!(self.keymap.bind_edit(key, val)),
+            ("options", "escdelay") => {
+                self.escdelay = match from_str(val) {
+                    Some(n) => n,
+                    None => return Err("expected a number"),
+                };
+            },
+            ("options", "download-dir") => self.download_dir = expand_home(val),
+            ("options", "call-input") => self.call_input = Some(expand_home(val)),
+            ("options", "call-output") => self.call_output = Some(expand_home(val)),
+            ("notify", "bell") => self.notifier.bell = try!(parse_bool(val)),
+            ("notify", "urgency") => self.notifier.urgency = try!(parse_bool(val)),
+            ("notify", "command") => {
+                self.notifier.command = if val.len() > 0 {
+                    Some(val.to_string())
+                } else {
+                    None
+                };
+            },
+            _ => return Err("unknown option"),
+        }
+        Ok(())
+    }
+}
+
+/// Parses a color of the form `#RRGGBB`.
+fn parse_rgb(s: &str) -> Result<u32, &'static str> {
+    if s.len() != 7 || !s.starts_with("#") {
+        return Err("expected a color of the form #RRGGBB");
+    }
+    match from_str_radix(s.slice_from(1), 16) {
+        Some(rgb) => Ok(rgb),
+        None => Err("expected a color of the form #RRGGBB"),
+    }
+}
+
+fn parse_bool(s: &str) -> Result<bool, &'static str> {
+    match s {
+        "true" | "yes" | "on" => Ok(true),
+        "false" | "no" | "off" => Ok(false),
+        _ => Err("expected a boolean"),
+    }
+}
+
+fn home() -> Path {
+    Path::new(getenv("HOME").unwrap_or(".".to_string()))
+}
+
+/// Replaces a leading `~/` in `s` with the home directory.
+fn expand_home(s: &str) -> Path {
+    if s.starts_with("~/") {
+        home().join(s.slice_from(2))
+    } else {
+        Path::new(s)
+    }
+}
+
+/// Returns `$XDG_DOWNLOAD_DIR` or `~/Downloads` if the former is not set.
+fn default_download_dir() -> Path {
+    match getenv("XDG_DOWNLOAD_DIR") {
+        Some(ref s) if s.len() > 0 => Path::new(s.as_slice()),
+        _ => home().join("Downloads"),
+    }
+}
+
+/// Returns the default location of the configuration file.
+///
+/// This is `$XDG_CONFIG_HOME/stannis/config` or `~/.config/stannis/config` if the
+/// former is not set.
+pub fn default_path() -> Path {
+    let base = match getenv("XDG_CONFIG_HOME") {
+        Some(ref s) if s.len() > 0 => Path::new(s.as_slice()),
+        _ => {
+            let home = getenv("HOME").unwrap_or(".".to_string());
+            Path::new(home).join(".config")
+        },
+    };
+    base.join_many(["stannis", "config"])
+}
+
+/// Loads the configuration file at `path`.
+///
+/// The file consists of `[section]` headers and `key = value` lines. Lines starting
+/// with `#` are ignored. A missing file is treated like an empty one.
+pub fn load(path: &Path) -> Result<Config, String> {
+    let mut config = Config::new();
+    if !path.exists() {
+        return Ok(config);
+    }
+    let file = match File::open(path) {
+        Ok(f) => f,
+        Err(e) => return Err(format!("{}: {}", path.display(), e)),
+    };
+    let mut section = String::new();
+    for (i, line) in BufferedReader::new(file).lines().enumerate() {
+        let line = match line {
+            Ok(l) => l,
+            Err(e) => return Err(format!("{}: {}", path.display(), e)),
+        };
+        let line = line.as_slice().trim();
+        if line.len() == 0 || line.starts_with("#") {
+            continue;
+        }
+        if line.starts_with("[") && line.ends_with("]") {
+            section = line.slice(1, line.len() - 1).trim().to_string();
+            continue;
+        }
+        let res = match line.find('=') {
+            Some(pos) => {
+                let key = line.slice_to(pos).trim();
+                let val = line.slice_from(pos + 1).trim();
+                config.set(section.as_slice(), key, val)
+            },
+            None => Err("expected `key = value`"),
+        };
+        match res {
+            Err(s) => return Err(format!("{}:{}: {}", path.display(), i + 1, s)),
+            _ => { },
+        }
+    }
+    Ok(config)
+}
@@ -1,25 +1,156 @@
-use tox::core::{ClientId};
+use tox::core::{UserStatus, UserStatusNone, UserStatusAway, UserStatusBusy};
 use nc = ncurses;
 use colors::*;
 use prompt::{Prompt};
+use chat::{Chat};
+use calls::{CallState};
+use keys;
+use keys::{Keymap};
+use history::{History};
+use pager::{Pager};
+use transfers::{Transfers, Active, Paused, Offered, Download};
+use utfbuf::{UtfBuf};
+use text::{lowercase, contains_lowercase, mentions};
 use commands;
-use commands::{Del};
+use commands::{Candidate, CompletionData};
+use term::cwidth::{StringWidth};
+use commands::{Del, Msg, GroupMsg, Accept, Decline, Leave, Undo, File, FileAccept,
+               FilePause, FileResume, FileCancel};
+
+use std::io::{IoResult};
 
 struct FriendRequest {
-    id: ClientId,
+    /// The number the request is accepted with.
+    number: uint,
+    /// The client id of whoever sent the request.
+    id: String,
     message: String,
 }
 
+#[deriving(PartialEq)]
+enum Presence {
+    Online,
+    Away,
+    Busy,
+    Offline,
+}
+
+impl Presence {
+    /// Friends are sorted by this.
+    fn rank(self) -> uint {
+        self as uint
+    }
+
+    fn color(self) -> i16 {
+        match self {
+            Online => COLOR_PAIR_ONLINE,
+            Away => COLOR_PAIR_AWAY,
+            Busy => COLOR_PAIR_BUSY,
+            Offline => COLOR_PAIR_OFFLINE,
+        }
+    }
+}
+
 struct Friend {
     id: i32,
+    name: String,
+    /// Our own name for the friend. Empty if not set.
+    alias: String,
+    note: String,
+    status: String,
+    online: bool,
+    user_status: UserStatus,
+    chat: Chat,
+    typing: bool,
+    call: Option<CallState>,
+    unread: Unread,
+}
+
+impl Friend {
+    fn display_name<'a>(&'a self) -> &'a str {
+        if self.alias.len() > 0 {
+            self.alias.as_slice()
+        } else {
+            self.name.as_slice()
+        }
+    }
+
+    fn presence(&self) -> Presence {
+        if !self.online {
+            return Offline;
+        }
+        match self.user_status {
+            UserStatusAway => Away,
+            UserStatusBusy => Busy,
+            _ => Online,
+        }
+    }
+}
+
+/// The state of tab completion in command mode.
+struct Completion {
+    /// The text before the word that's being completed.
+    prefix: String,
+    /// The text after it.
+    suffix: String,
+    candidates: Vec<Candidate>,
+    /// The candidate that's currently inserted.
+    index: uint,
+}
+
+/// The state of a reverse incremental search through the history.
+struct HistorySearch {
+    query: String,
+    /// The entry that matches the query.
+    found: Option<uint>,
+    /// The text in the prompt before the search started.
+    original: String,
+    utfbuf: UtfBuf,
+}
+
+/// Our own name and status.
+struct Me {
     name: String,
     status: String,
+    user_status: UserStatus,
 }
 
 struct Group {
     id: i32,
+    title: String,
+    peers: Vec<String>,
+    chat: Chat,
+    unread: Unread,
 }
 
+/// Messages that arrived while the conversation wasn't on the screen.
+struct Unread {
+    count: uint,
+    /// Whether one of them contains our name.
+    mentioned: bool,
+}
+
+impl Unread {
+    fn new() -> Unread {
+        Unread {
+            count: 0,
+            mentioned: false,
+        }
+    }
+
+    fn add(&mut self, mentioned: bool) {
+        self.count += 1;
+        self.mentioned = self.mentioned || mentioned;
+    }
+}
+
+#[deriving(PartialEq)]
+enum ChatId {
+    FriendChat(i32),
+    GroupChat(i32),
+}
+
+#[deriving(PartialEq)]
 enum Mode {
     CommandMode,
     SearchMode,
@@ -41,12 +172,44 @@ impl Mode {
 pub struct View<'a> {
     requests: Vec<FriendRequest>,
     friends: Vec<Friend>,
-    groups: uint,
+    groups: Vec<Group>,
     top: uint,
     selected: Row<'a>,
     mode: Mode,
     prompt: Prompt,
     needs_resize: bool,
+    /// The conversation that is open.
+    chat: Option<ChatId>,
+    me: Me,
+    keymap: Keymap,
+    completion: Option<Completion>,
+    /// Addresses we've sent friend requests to. `:add` completes them.
+    addresses: Vec<String>,
+    command_history: History,
+    chat_history: History,
+    /// Why the last line couldn't be added to the history file.
+    history_error: Option<String>,
+    search: Option<HistorySearch>,
+    /// The lower case pattern of the last search in the list.
+    pattern: String,
+    /// The row that was selected before the current search started.
+    pattern_origin: Row<'a>,
+    /// Text shown instead of the list, e.g. the help.
+    pager: Option<Pager>,
+    /// The row of the selected transfer.
+    transfer: uint,
+    /// Whether the transfers are shown instead of the list.
+    show_transfers: bool,
+}
+
+/// What keys act on. See `View::focus`.
+#[deriving(PartialEq)]
+pub struct Focus<'a> {
+    mode: Mode,
+    selected: Row<'a>,
+    chat: bool,
+    pager: bool,
+    transfers: bool,
 }
 
 #[deriving(Eq, PartialEq)]
@@ -63,12 +226,157 @@ impl<'a> View<'a> {
         let friend = Friend {
             id: id,
             name: "anonymous".to_string(),
+            alias: String::new(),
+            note: String::new(),
             status: "".to_string(),
+            online: false,
+            user_status: UserStatusNone,
+            chat: Chat::new(),
+            typing: false,
+            call: None,
+            unread: Unread::new(),
         };
         self.friends.push(friend);
-        if self.friends.len() + self.requests.len() + self.groups == 1 {
+        if self.friends.len() + self.requests.len() + self.groups.len() == 1 {
             self.selected = FriendRow(0);
         }
+        self.sort_friends();
+    }
+
+    pub fn tox_status_message(&mut self, id: i32, msg: String) {
+        match self.friends.mut_iter().find(|f| f.id == id) {
+            Some(f) => f.status = msg,
+            None => { },
+        }
+    }
+
+    pub fn tox_user_status(&mut self, id: i32, status: UserStatus) {
+        match self.friends.mut_iter().find(|f| f.id == id) {
+            Some(f) => f.user_status = status,
+            None => return,
+        }
+        self.sort_friends();
+    }
+
+    pub fn tox_connection_status(&mut self, id: i32, online: bool) {
+        match self.friends.mut_iter().find(|f| f.id == id) {
+            Some(f) => {
+                f.online = online;
+                if !online {
+                    f.typing = false;
+                }
+            },
+            None => return,
+        }
+        self.sort_friends();
+    }
+
+    /// Sorts the friends by presence while keeping the selected friend selected.
+    fn sort_friends(&mut self) {
+        let selected = match self.selected {
+            FriendRow(i) => Some(self.friends.get(i).id),
+            _ => None,
+        };
+        self.friends.sort_by(|a, b| a.presence().rank().cmp(&b.presence().rank()));
+        match selected {
+            Some(id) => {
+                let pos = self.friends.iter().position(|f| f.id == id).unwrap();
+                self.selected = FriendRow(pos);
+                self.show_selected();
+            },
+            None => { },
+        }
+    }
+
+    /// Number of lines available to the list.
+    ///
+    /// The first line shows our own name and the last two lines are used by the prompt
+    /// and the status line.
+    fn height(&self) -> uint {
+        if nc::LINES > 3 { nc::LINES as uint - 3 } else { 0 }
+    }
+
+    /// Scrolls the list so that the selected row is visible.
+    fn show_selected(&mut self) {
+        let abs = self.absolute(self.selected);
+        let height = self.height();
+        if abs < self.top || height == 0 {
+            self.top = abs;
+        } else if abs >= self.top + height {
+            self.top = abs + 1 - height;
+        }
+    }
+
+    pub fn remember_address(&mut self, addr: String) {
+        if !self.addresses.contains(&addr) {
+            self.addresses.push(addr);
+        }
+    }
+
+    /// Loads the command and chat histories from `dir`.
+    pub fn load_history(&mut self, dir: &Path) {
+        self.command_history = History::load(dir.join("command-history"));
+        self.chat_history = History::load(dir.join("chat-history"));
+    }
+
+    fn history_saved(&mut self, res: IoResult<()>) {
+        match res {
+            Err(e) => self.history_error = Some(format!("could not save history: {}", e)),
+            _ => { },
+        }
+    }
+
+    /// Returns why the history couldn't be saved since the last call.
+    pub fn take_history_error(&mut self) -> Option<String> {
+        self.history_error.take()
+    }
+
+    pub fn set_keymap(&mut self, keymap: Keymap) {
+        self.keymap = keymap;
+    }
+
+    pub fn set_self(&mut self, name: String, status: String, user_status: UserStatus) {
+        self.me = Me {
+            name: name,
+            status: status,
+            user_status: user_status,
+        };
+    }
+
+    pub fn tox_request(&mut self, number: uint, id: String, message: String) {
+        let request = FriendRequest {
+            number: number,
+            id: id,
+            message: message,
+        };
+        self.requests.push(request);
+        match self.selected {
+            NoRow => self.selected = RequestRow(0),
+            _ => { },
+        }
+    }
+
+    /// Removes the request with the number `number`.
+    pub fn del_request(&mut self, number: uint) -> Result<(), &'static str> {
+        let idx = match self.requests.iter().position(|r| r.number == number) {
+            Some(i) => i,
+            None => return Err("unknown request"),
+        };
+        match self.selected {
+            RequestRow(i) if i > idx => self.selected = RequestRow(i-1),
+            RequestRow(i) if i == idx => {
+                self.selected = match self.close(self.selected) {
+                    RequestRow(j) if j > idx => RequestRow(j-1),
+                    r => r,
+                };
+            },
+            _ => { },
+        }
+        self.requests.remove(idx);
+        if self.absolute(self.selected) < self.top {
+            self.top = self.absolute(self.selected);
+        }
+        Ok(())
     }
 
     pub fn tox_name_change(&mut self, id: i32, name: String) {
@@ -79,8 +387,227 @@ impl<'a> View<'a> {
         self.friends.get_mut(pos).name = name;
     }
 
+    pub fn set_alias(&mut self, id: i32, alias: String) {
+        match self.friends.mut_iter().find(|f| f.id == id) {
+            Some(f) => f.alias = alias,
+            None => { },
+        }
+    }
+
+    pub fn set_note(&mut self, id: i32, note: String) {
+        match self.friends.mut_iter().find(|f| f.id == id) {
+            Some(f) => f.note = note,
+            None => { },
+        }
+    }
+
+    /// Returns the ids and aliases of the friends that have one.
+    pub fn aliases(&self) -> Vec<(i32, String)> {
+        self.friends.iter().filter(|f| f.alias.len() > 0).map(|f| {
+            (f.id, f.alias.clone())
+        }).collect()
+    }
+
+    pub fn tox_message(&mut self, id: i32, msg: String) {
+        let shown = self.showing(FriendChat(id));
+        let mentioned = mentions(msg.as_slice(), self.me.name.as_slice());
+        match self.friends.mut_iter().find(|f| f.id == id) {
+            Some(f) => {
+                let name = f.display_name().to_string();
+                f.chat.push(name.as_slice(), msg);
+                if !shown {
+                    f.unread.add(mentioned);
+                }
+            },
+            None => { },
+        }
+    }
+
+    pub fn tox_action(&mut self, id: i32, msg: String) {
+        let shown = self.showing(FriendChat(id));
+        let mentioned = mentions(msg.as_slice(), self.me.name.as_slice());
+        match self.friends.mut_iter().find(|f| f.id == id) {
+            Some(f) => {
+                let name = f.display_name().to_string();
+                f.chat.push_action(name.as_slice(), msg);
+                if !shown {
+                    f.unread.add(mentioned);
+                }
+            },
+            None => { },
+        }
+    }
+
+    pub fn tox_sent(&mut self, id: i32, msg: String, receipt: u32) {
+        match self.friends.mut_iter().find(|f| f.id == id) {
+            Some(f) => f.chat.push_sent("me", msg, receipt),
+            None => { },
+        }
+    }
+
+    pub fn tox_read_receipt(&mut self, id: i32, receipt: u32) {
+        match self.friends.mut_iter().find(|f| f.id == id) {
+            Some(f) => f.chat.delivered(receipt),
+            None => { },
+        }
+    }
+
+    pub fn tox_typing(&mut self, id: i32, typing: bool) {
+        match self.friends.mut_iter().find(|f| f.id == id) {
+            Some(f) => f.typing = typing,
+            None => { },
+        }
+    }
+
+    pub fn set_call(&mut self, id: i32, call: Option<CallState>) {
+        match self.friends.mut_iter().find(|f| f.id == id) {
+            Some(f) => f.call = call,
+            None => { },
+        }
+    }
+
+    /// Returns whether the conversation `chat` is on the screen.
+    fn showing(&self, chat: ChatId) -> bool {
+        self.pager.is_none() && !self.show_transfers && self.chat == Some(chat)
+    }
+
+    /// Returns whether the conversation with the friend `id` is on the screen.
+    pub fn showing_chat(&self, id: i32) -> bool {
+        self.showing(FriendChat(id))
+    }
+
+    /// Returns the number of unread messages in all conversations and whether we were
+    /// mentioned in one of them.
+    pub fn unread(&self) -> (uint, bool) {
+        let unread = self.friends.iter().map(|f| &f.unread)
+                         .chain(self.groups.iter().map(|g| &g.unread));
+        unread.fold((0, false), |(n, m), u| (n + u.count, m || u.mentioned))
+    }
+
+    /// Returns the friend we're writing a message to, if any.
+    pub fn typing_to(&self) -> Option<i32> {
+        if self.prompt.text().len() == 0 {
+            return None;
+        }
+        match (self.mode, self.chat) {
+            (InsertMode, Some(FriendChat(id))) => Some(id),
+            _ => None,
+        }
+    }
+
+    pub fn tox_group_invite(&mut self, id: i32) {
+        match self.friends.mut_iter().find(|f| f.id == id) {
+            Some(f) => {
+                let msg = format!("invites you to a group chat (:join {})", id);
+                let name = f.display_name().to_string();
+                f.chat.push_action(name.as_slice(), msg);
+            },
+            None => { },
+        }
+    }
+
+    pub fn tox_add_group(&mut self, id: i32) {
+        let group = Group {
+            id: id,
+            title: format!("Groupchat {}", id),
+            peers: Vec::new(),
+            chat: Chat::new(),
+            unread: Unread::new(),
+        };
+        self.groups.push(group);
+        if self.friends.len() + self.requests.len() + self.groups.len() == 1 {
+            self.selected = GroupRow(0);
+        }
+    }
+
+    pub fn tox_group_message(&mut self, id: i32, peer: &str, msg: String) {
+        let shown = self.showing(GroupChat(id));
+        let mentioned = mentions(msg.as_slice(), self.me.name.as_slice());
+        match self.groups.mut_iter().find(|g| g.id == id) {
+            Some(g) => {
+                g.chat.push(peer, msg);
+                if !shown {
+                    g.unread.add(mentioned);
+                }
+            },
+            None => { },
+        }
+    }
+
+    pub fn tox_group_peers(&mut self, id: i32, peers: Vec<String>) {
+        match self.groups.mut_iter().find(|g| g.id == id) {
+            Some(g) => g.peers = peers,
+            None => { },
+        }
+    }
+
+    pub fn del_group(&mut self, id: i32) -> Result<(), &'static str> {
+        let pos = match self.groups.iter().position(|g| g.id == id) {
+            Some(i) => i,
+            None => return Err("unknown group"),
+        };
+        if self.chat == Some(GroupChat(id)) {
+            self.close_chat();
+        }
+        match self.selected {
+            GroupRow(i) if i > pos => self.selected = GroupRow(i-1),
+            GroupRow(i) if i == pos => {
+                self.selected = match self.close(self.selected) {
+                    GroupRow(j) if j > pos => GroupRow(j-1),
+                    r => r,
+                };
+            },
+            _ => { },
+        }
+        self.groups.remove(pos);
+        if self.absolute(self.selected) < self.top {
+            self.top = self.absolute(self.selected);
+        }
+        Ok(())
+    }
+
+    /// Returns the name of the friend `id` for messages.
+    pub fn friend_name(&self, id: i32) -> String {
+        match self.friends.iter().find(|f| f.id == id) {
+            Some(f) => format!("{} ({})", f.display_name(), id),
+            None => format!("friend {}", id),
+        }
+    }
+
+    fn chat_title(&self) -> Option<String> {
+        match self.chat {
+            Some(FriendChat(id)) => {
+                self.friends.iter().find(|f| f.id == id).map(|f| {
+                    if f.note.len() > 0 {
+                        format!("Chat with {} ({})", f.display_name(), f.note)
+                    } else {
+                        format!("Chat with {}", f.display_name())
+                    }
+                })
+            },
+            Some(GroupChat(id)) => {
+                self.groups.iter().find(|g| g.id == id).map(|g| {
+                    format!("{} ({})", g.title, g.peers.connect(", "))
+                })
+            },
+            None => None,
+        }
+    }
+
+    fn chat_mut<'b>(&'b mut self) -> Option<&'b mut Chat> {
+        match self.chat {
+            Some(FriendChat(id)) => {
+                self.friends.mut_iter().find(|f| f.id == id).map(|f| &mut f.chat)
+            },
+            Some(GroupChat(id)) => {
+                self.groups.mut_iter().find(|g| g.id == id).map(|g| &mut g.chat)
+            },
+            None => None,
+        }
+    }
+
     fn absolute(&self, row: Row) -> uint {
-        match (row, self.requests.len(), self.groups) {
+        match (row, self.requests.len(), self.groups.len()) {
             (RequestRow(i), _, _) => 1+i,
             (GroupRow(i), r, _) => 1+i+r+((r != 0) as uint),
             (FriendRow(i), r, g) => 1+i+r+g+((r != 0) as uint)+((g != 0) as uint),
@@ -98,12 +625,30 @@ impl<'a> View<'a> {
         View {
             requests: Vec::new(),
             friends: Vec::new(),
-            groups: 0,
+            groups: Vec::new(),
             top: 0,
             selected: NoRow,
             mode: NormalMode,
             prompt: prompt,
             needs_resize: true,
+            chat: None,
+            me: Me {
+                name: String::new(),
+                status: String::new(),
+                user_status: UserStatusNone,
+            },
+            keymap: Keymap::new(),
+            completion: None,
+            addresses: Vec::new(),
+            command_history: History::new(),
+            chat_history: History::new(),
+            history_error: None,
+            search: None,
+            pattern: String::new(),
+            pattern_origin: NoRow,
+            pager: None,
+            transfer: 0,
+            show_transfers: false,
         }
     }
 
@@ -128,8 +673,8 @@ impl<'a> View<'a> {
             FriendRow(i) => {
                 if i > 0 {
                     self.selected = FriendRow(i-1);
-                } else if self.groups > 0 {
-                    self.selected = GroupRow(self.groups - 1);
+                } else if self.groups.len() > 0 {
+                    self.selected = GroupRow(self.groups.len() - 1);
                 } else if self.requests.len() > 0 {
                     self.selected = RequestRow(self.requests.len() - 1);
                 }
@@ -146,14 +691,14 @@ impl<'a> View<'a> {
             RequestRow(i) => {
                 if i < self.requests.len() - 1 {
                     self.selected = RequestRow(i+1);
-                } else  if self.groups > 0 {
+                } else  if self.groups.len() > 0 {
                     self.selected = GroupRow(0);
                 } else if self.friends.len() > 0 {
                     self.selected = FriendRow(0);
                 }
             },
             GroupRow(i) => {
-                if i < self.groups - 1 {
+                if i < self.groups.len() - 1 {
                     self.selected = GroupRow(i+1);
                 } else if self.friends.len() > 0 {
                     self.selected = FriendRow(0);
@@ -166,37 +711,37 @@ impl<'a> View<'a> {
             },
             _ => { },
         }
-        if self.absolute(self.selected) - self.top >= nc::LINES as uint - 3 {
-            self.top = self.absolute(self.selected) - nc::LINES as uint + 3;
-        }
+        self.show_selected();
     }
 
     fn do_resize(&mut self) {
         self.prompt.resize();
-        if nc::LINES <= 2 {
-            self.top = self.absolute(self.selected);
-        } else if self.absolute(self.selected) - self.top + 3 > nc::LINES as uint {
-            self.top = self.absolute(self.selected) - nc::LINES as uint + 3;
-        }
+        self.show_selected();
     }
 
-    pub fn update(&mut self) {
+    pub fn update(&mut self, transfers: &Transfers) {
         if self.needs_resize {
             self.do_resize();
             self.needs_resize = false;
         }
-        let mut num = 0;
-        for (i, row) in self.iter().skip(self.top).take(nc::LINES as uint - 2).enumerate() {
-            match row {
-                Header(s) => self.print_header(i as i32, s),
-                _ => self.print_entry(i as i32, row),
+        if self.pager.is_some() {
+            self.update_pager();
+        } else if self.show_transfers {
+            self.print_header(0, "Transfers");
+            if nc::LINES > 3 {
+                transfers.draw(1, nc::LINES as uint - 3, self.transfer);
+            }
+        } else {
+            match self.chat_title() {
+                Some(title) => self.update_chat(title.as_slice()),
+                None => self.update_list(),
             }
-            num += 1;
         }
-        normal!(COLOR_PAIR_DEFAULT);
-        for i in range(num, nc::LINES as uint - 2) {
-            nc::move(i as i32, 0);
-            nc::clrtoeol();
+        match (self.mode, &self.completion) {
+            (CommandMode, &Some(ref c)) if c.candidates.len() > 1 && nc::LINES > 3 => {
+                self.print_completion(nc::LINES - 3, c);
+            },
+            _ => { },
         }
         self.prompt.draw(nc::LINES - 2);
         nc::refresh();
@@ -206,6 +751,126 @@ impl<'a> View<'a> {
         };
     }
 
+    fn update_chat(&mut self, title: &str) {
+        self.mark_read();
+        self.print_header(0, title);
+        // Who's typing is shown in the line above the prompt.
+        let typing = match self.chat {
+            Some(FriendChat(id)) => self.friends.iter().find(|f| f.id == id && f.typing),
+            _ => None,
+        }.map(|f| format!("{} is typing…", f.display_name()));
+        let height = match typing {
+            Some(_) => nc::LINES - 4,
+            None => nc::LINES - 3,
+        };
+        if height > 0 {
+            match self.chat_mut() {
+                Some(chat) => chat.draw(1, height as uint),
+                None => { },
+            }
+        }
+        match typing {
+            Some(ref text) if nc::LINES > 3 => {
+                normal!(COLOR_PAIR_OFFLINE);
+                nc::mvaddstr(nc::LINES - 3, 0, text.as_slice());
+                nc::clrtoeol();
+                normal!(COLOR_PAIR_DEFAULT);
+            },
+            _ => { },
+        }
+    }
+
+    /// Forgets the unread messages of the conversation on the screen.
+    fn mark_read(&mut self) {
+        match self.chat {
+            Some(FriendChat(id)) => match self.friends.mut_iter().find(|f| f.id == id) {
+                Some(f) => f.unread = Unread::new(),
+                None => { },
+            },
+            Some(GroupChat(id)) => match self.groups.mut_iter().find(|g| g.id == id) {
+                Some(g) => g.unread = Unread::new(),
+                None => { },
+            },
+            None => { },
+        }
+    }
+
+    fn update_pager(&mut self) {
+        let title = self.pager.as_ref().unwrap().title.clone();
+        self.print_header(0, title.as_slice());
+        if nc::LINES > 3 {
+            self.pager.as_mut().unwrap().draw(1, nc::LINES as uint - 3);
+        }
+    }
+
+    fn update_list(&mut self) {
+        if nc::LINES <= 2 {
+            return;
+        }
+        self.print_self(0);
+        let height = self.height();
+        let mut num = 0;
+        for (i, row) in self.iter().skip(self.top).take(height).enumerate() {
+            match row {
+                Header(s) => self.print_header(i as i32 + 1, s),
+                _ => self.print_entry(i as i32 + 1, row),
+            }
+            num += 1;
+        }
+        normal!(COLOR_PAIR_DEFAULT);
+        for i in range(num, height) {
+            nc::move(i as i32 + 1, 0);
+            nc::clrtoeol();
+        }
+    }
+
+    fn print_self(&self, y: i32) {
+        let presence = match self.me.user_status {
+            UserStatusAway => Away,
+            UserStatusBusy => Busy,
+            _ => Online,
+        };
+        nc::move(y, 0);
+        normal!(presence.color());
+        nc::addch(nc::ACS_BULLET());
+        bold!(COLOR_PAIR_DEFAULT);
+        nc::addstr(format!(" {}", self.me.name).as_slice());
+        normal!(COLOR_PAIR_DEFAULT);
+        nc::addstr(format!("   {}", self.me.status).as_slice());
+        nc::clrtoeol();
+    }
+
+    fn print_completion(&self, y: i32, c: &Completion) {
+        let cols = nc::COLS as uint;
+        // Find the first candidate so that the current one is visible.
+        let mut first = c.index;
+        let mut width = c.candidates.get(first).display.as_slice().width() + 2;
+        while first > 0 {
+            let w = c.candidates.get(first - 1).display.as_slice().width() + 2;
+            if width + w > cols {
+                break;
+            }
+            width += w;
+            first -= 1;
+        }
+        normal!(COLOR_PAIR_DEFAULT);
+        nc::move(y, 0);
+        let mut x = 0;
+        for (i, candidate) in c.candidates.iter().enumerate().skip(first) {
+            let w = candidate.display.as_slice().width() + 2;
+            if x + w > cols {
+                break;
+            }
+            if i == c.index {
+                bold!(COLOR_PAIR_SELECTED);
+            }
+            nc::addstr(format!(" {} ", candidate.display).as_slice());
+            normal!(COLOR_PAIR_DEFAULT);
+            x += w;
+        }
+        nc::clrtoeol();
+    }
+
     fn print_header(&self, y: i32, text: &str) {
         bold!(COLOR_PAIR_HEADER);
         nc::mvaddstr(y, 0, text);
@@ -217,30 +882,142 @@ impl<'a> View<'a> {
         normal!(COLOR_PAIR_DEFAULT);
     }
 
-    fn print_entry(&self, y: i32, row: Row) {
+    /// Sets the color of the text in `row`.
+    fn row_color(&self, row: Row, matches: bool) {
         if self.selected == row {
             bold!(COLOR_PAIR_SELECTED);
+        } else if matches {
+            normal!(COLOR_PAIR_MATCH);
+        } else {
+            normal!(COLOR_PAIR_DEFAULT);
         }
+    }
+
+    /// Prints the number of unread messages in `row`, highlighted if we were
+    /// mentioned.
+    fn print_unread(&self, unread: &Unread, row: Row, matches: bool) {
+        if unread.count == 0 {
+            return;
+        }
+        if unread.mentioned {
+            bold!(COLOR_PAIR_MENTION);
+        } else if self.selected != row {
+            bold!(COLOR_PAIR_HEADER);
+        }
+        nc::addstr(format!(" ({})", unread.count).as_slice());
+        self.row_color(row, matches);
+    }
+
+    fn print_entry(&self, y: i32, row: Row) {
+        let matches = self.pattern.len() > 0 && self.matches(row);
+        self.row_color(row, matches);
         nc::mvaddch(y, 0, ' ' as u32);
         match row {
             RequestRow(i) => {
-                nc::addstr(self.requests.get(i as uint).message.as_slice());
+                let request = self.requests.get(i);
+                nc::addstr(format!("{}  {}   {}", request.number,
+                                   request.id.as_slice().slice_to(8),
+                                   request.message).as_slice());
             },
             GroupRow(i) => {
-                nc::addstr(format!("Groupchat {}", i).as_slice());
+                let group = self.groups.get(i);
+                nc::addstr(group.title.as_slice());
+                self.print_unread(&group.unread, row, matches);
+                nc::addstr(format!("   {} peers", group.peers.len()).as_slice());
             },
             FriendRow(i) => {
                 let friend = self.friends.get(i);
-                nc::addstr(format!("{}   {}", friend.name, friend.status).as_slice());
+                normal!(friend.presence().color());
+                nc::addch(nc::ACS_BULLET());
+                self.row_color(row, matches);
+                nc::addstr(format!(" {}", friend.display_name()).as_slice());
+                self.print_unread(&friend.unread, row, matches);
+                nc::addstr(format!("   {}", friend.status).as_slice());
+                match friend.call {
+                    Some(call) => {
+                        nc::addstr(format!("   [{}]", call.fmt()).as_slice());
+                    },
+                    None => { },
+                }
             }
             _ => { },
         }
         nc::clrtoeol();
-        if self.selected == row {
-            normal!(COLOR_PAIR_DEFAULT);
+        normal!(COLOR_PAIR_DEFAULT);
+    }
+
+    /// Returns whether `row` matches the search pattern.
+    fn matches(&self, row: Row) -> bool {
+        let pattern = self.pattern.as_slice();
+        match row {
+            RequestRow(i) => {
+                let request = self.requests.get(i);
+                contains_lowercase(request.message.as_slice(), pattern)
+                    || contains_lowercase(request.id.as_slice(), pattern)
+            },
+            GroupRow(i) => {
+                let group = self.groups.get(i);
+                contains_lowercase(group.title.as_slice(), pattern)
+                    || group.peers.iter().any(|p| contains_lowercase(p.as_slice(), pattern))
+            },
+            FriendRow(i) => {
+                let friend = self.friends.get(i);
+                contains_lowercase(friend.name.as_slice(), pattern)
+                    || contains_lowercase(friend.alias.as_slice(), pattern)
+                    || contains_lowercase(friend.note.as_slice(), pattern)
+                    || contains_lowercase(friend.status.as_slice(), pattern)
+            },
+            _ => false,
+        }
+    }
+
+    /// Returns the next row after `from` that matches the search pattern.
+    ///
+    /// The search wraps around at the end of the list. If `inclusive` is set, `from`
+    /// itself is considered first.
+    fn find_match(&self, from: Row, forward: bool, inclusive: bool) -> Option<Row<'a>> {
+        if self.pattern.len() == 0 {
+            return None;
+        }
+        self.find_row(from, forward, inclusive, |r| self.matches(r))
+    }
+
+    /// Returns whether `row` has unread messages.
+    fn has_unread(&self, row: Row) -> bool {
+        match row {
+            GroupRow(i) => self.groups.get(i).unread.count > 0,
+            FriendRow(i) => self.friends.get(i).unread.count > 0,
+            _ => false,
         }
     }
 
+    /// Like `find_match` but returns the next row for which `f` returns true.
+    fn find_row(&self, from: Row, forward: bool, inclusive: bool,
+                f: |Row<'a>| -> bool) -> Option<Row<'a>> {
+        let mut rows: Vec<Row<'a>> = self.iter().filter(|r| {
+            match *r {
+                Header(_) => false,
+                _ => true,
+            }
+        }).map(|r| unbound(r)).collect();
+        if !forward {
+            rows.reverse();
+        }
+        let start = match rows.iter().position(|&r| r == from) {
+            Some(i) if inclusive => i,
+            Some(i) => i + 1,
+            None => 0,
+        };
+        let len = rows.len();
+        for i in range(0, len) {
+            let row = *rows.get((start + i) % len);
+            if f(row) {
+                return Some(row);
+            }
+        }
+        None
+    }
+
     fn iter<'b>(&'b self) -> RowIter<'b> {
         RowIter {
             view: self,
@@ -249,23 +1026,318 @@ impl<'a> View<'a> {
         }
     }
 
-    pub fn handle_key(&mut self, key: i32) -> Option<commands::Result> {
+    /// Shows `lines` until the user closes the pager.
+    pub fn show_pager(&mut self, title: String, lines: Vec<String>) {
+        self.pager = Some(Pager::new(title, lines));
+    }
+
+    pub fn scroll_pager_to_end(&mut self) {
+        match self.pager {
+            Some(ref mut p) => p.scroll_to_end(),
+            None => { },
+        }
+    }
+
+    fn handle_pager_key(&mut self, key: i32) {
+        if key == 'q' as i32 || key == 27 {
+            self.pager = None;
+            return;
+        }
+        let pager = self.pager.as_mut().unwrap();
+        let page = pager.page();
+        if key == ' ' as i32 || key == nc::KEY_NPAGE {
+            pager.scroll_down(page);
+        } else if key == 'b' as i32 || key == nc::KEY_PPAGE {
+            pager.scroll_up(page);
+        } else {
+            match self.keymap.normal(key) {
+                Some(keys::Down) => pager.scroll_down(1),
+                Some(keys::Up) => pager.scroll_up(1),
+                _ => { },
+            }
+        }
+    }
+
+    pub fn show_transfers(&mut self) {
+        self.show_transfers = true;
+    }
+
+    /// Returns what keys act on: the mode, the selected row and what's shown.
+    pub fn focus(&self) -> Focus<'a> {
+        Focus {
+            mode: self.mode,
+            selected: self.selected,
+            chat: self.chat.is_some(),
+            pager: self.pager.is_some(),
+            transfers: self.show_transfers,
+        }
+    }
+
+    /// Whether keys act on the list, i.e. we're in normal mode and nothing is shown
+    /// instead of the list.
+    pub fn in_normal_mode(&self) -> bool {
+        self.mode == NormalMode && self.pager.is_none() && !self.show_transfers
+    }
+
+    /// Returns the friend we're chatting with or, if there's no chat, the selected
+    /// friend.
+    pub fn current_friend(&self) -> Option<i32> {
+        match (self.chat, self.selected) {
+            (Some(FriendChat(id)), _) => Some(id),
+            (None, FriendRow(i)) => Some(self.friends.get(i).id),
+            _ => None,
+        }
+    }
+
+    fn handle_transfers_key(&mut self, key: i32,
+                            transfers: &Transfers) -> Option<commands::Result> {
+        if key == 'q' as i32 || key == 27 {
+            self.show_transfers = false;
+            return None;
+        }
+        let (id, state, direction) = match transfers.nth(self.transfer) {
+            Some(t) => (t.id, t.state, t.direction),
+            None => return None,
+        };
+        let action = if key == 'a' as i32 {
+            match (state, direction) {
+                (Offered, Download) => FileAccept,
+                _ => return None,
+            }
+        } else if key == 'p' as i32 {
+            match state {
+                Active => FilePause,
+                Paused => FileResume,
+                _ => return None,
+            }
+        } else if key == 'c' as i32 {
+            FileCancel
+        } else {
+            match self.keymap.normal(key) {
+                Some(keys::Down) if self.transfer + 1 < transfers.len() => {
+                    self.transfer += 1;
+                },
+                Some(keys::Up) if self.transfer > 0 => self.transfer -= 1,
+                _ => { },
+            }
+            return None;
+        };
+        Some(Ok(File(action, id)))
+    }
+
+    /// Handles a key. `transfers` are the transfers shown in the transfers pane.
+    pub fn handle_key(&mut self, key: i32,
+                      transfers: &Transfers) -> Option<commands::Result> {
+        if self.pager.is_some() {
+            self.handle_pager_key(key);
+            return None;
+        }
+        if self.show_transfers {
+            return self.handle_transfers_key(key, transfers);
+        }
         match self.mode {
             NormalMode => self.handle_normal_mode_key(key),
             CommandMode => self.handle_command_mode_key(key),
-            _ => None,
+            InsertMode => self.handle_insert_mode_key(key),
+            SearchMode => self.handle_search_mode_key(key),
         }
     }
 
     fn set_mode(&mut self, mode: Mode) {
         self.mode = mode;
+        self.search = None;
+        self.command_history.reset();
+        self.chat_history.reset();
         self.prompt.set_prefix(mode.fmt());
     }
 
+    /// Returns the history of the current mode.
+    fn history<'b>(&'b mut self) -> &'b mut History {
+        match self.mode {
+            InsertMode => &mut self.chat_history,
+            _ => &mut self.command_history,
+        }
+    }
+
+    fn prompt_key(&mut self, key: i32) {
+        match self.keymap.edit(key) {
+            Some(keys::HistoryPrev) => {
+                let text = self.prompt.text().to_string();
+                let entry = self.history().prev(text.as_slice()).map(|s| s.to_string());
+                match entry {
+                    Some(e) => self.prompt.set_text(e.as_slice()),
+                    None => { },
+                }
+            },
+            Some(keys::HistoryNext) => {
+                let entry = self.history().next().map(|s| s.to_string());
+                match entry {
+                    Some(e) => self.prompt.set_text(e.as_slice()),
+                    None => { },
+                }
+            },
+            Some(keys::HistorySearch) => {
+                self.search = Some(HistorySearch {
+                    query: String::new(),
+                    found: None,
+                    original: self.prompt.text().to_string(),
+                    utfbuf: UtfBuf::new(),
+                });
+                let len = self.history().len();
+                self.search_history(len);
+            },
+            Some(action) => self.prompt.edit(action),
+            None => self.prompt.key(key),
+        }
+    }
+
+    /// Looks for the last history entry before `before` that matches the search.
+    fn search_history(&mut self, before: uint) {
+        let query = match self.search {
+            Some(ref s) => s.query.clone(),
+            None => return,
+        };
+        let found = self.history().search(query.as_slice(), before);
+        let failed = match found {
+            Some(i) => {
+                let entry = self.history().get(i).to_string();
+                self.prompt.set_text(entry.as_slice());
+                self.search.as_mut().unwrap().found = Some(i);
+                false
+            },
+            None => query.len() > 0,
+        };
+        let prefix = if failed {
+            format!("(failed reverse-i-search)`{}': ", query)
+        } else {
+            format!("(reverse-i-search)`{}': ", query)
+        };
+        self.prompt.set_prefix(prefix.as_slice());
+    }
+
+    fn end_search(&mut self) {
+        self.search = None;
+        self.prompt.set_prefix(self.mode.fmt());
+    }
+
+    /// Handles a key during a history search.
+    ///
+    /// Returns the key if it ends the search and should be handled as usual.
+    fn handle_search_key(&mut self, key: i32) -> Option<i32> {
+        if key == 27 {
+            let original = self.search.take().unwrap().original;
+            self.prompt.set_prefix(self.mode.fmt());
+            self.prompt.set_text(original.as_slice());
+            return None;
+        }
+        if key == '\r' as i32 {
+            self.end_search();
+            return Some(key);
+        }
+        let len = self.history().len();
+        match self.keymap.edit(key) {
+            Some(keys::HistorySearch) => {
+                // Look for an older entry
+                let before = self.search.as_ref().unwrap().found.unwrap_or(len);
+                self.search_history(before);
+                return None;
+            },
+            Some(keys::DelBackward) => {
+                self.search.as_mut().unwrap().query.pop_char();
+                self.search_history(len);
+                return None;
+            },
+            Some(_) => {
+                self.end_search();
+                return Some(key);
+            },
+            None => { },
+        }
+        if key as u32 > 0xFF {
+            return None;
+        }
+        let before = {
+            let search = self.search.as_mut().unwrap();
+            let c = match search.utfbuf.push(key as u8) {
+                Some(c) => c,
+                None => return None,
+            };
+            if (c as u32) < 0x20 {
+                return None;
+            }
+            search.query.push_char(c);
+            // The current entry might still match.
+            search.found.map(|i| i + 1).unwrap_or(len)
+        };
+        self.search_history(before);
+        None
+    }
+
+    fn complete(&mut self) {
+        match self.completion {
+            Some(ref mut c) => {
+                c.index = (c.index + 1) % c.candidates.len();
+                let text = format!("{}{}", c.prefix, c.candidates.get(c.index).text);
+                self.prompt.set_text_cursor(text.as_slice(), c.suffix.as_slice());
+                return;
+            },
+            None => { },
+        }
+        let data = CompletionData {
+            friends: self.friends.iter().map(|f| {
+                (f.id, f.display_name().to_string())
+            }).collect(),
+            groups: self.groups.iter().map(|g| (g.id, g.title.clone())).collect(),
+            addresses: self.addresses.clone(),
+        };
+        let (start, end, candidates) = commands::complete(self.prompt.text(),
+                                                          self.prompt.cursor(), &data);
+        let prefix = self.prompt.text().slice_to(start).to_string();
+        let suffix = self.prompt.text().slice_from(end).to_string();
+        match candidates.len() {
+            0 => { },
+            1 => {
+                let text = candidates.get(0).text.as_slice();
+                // Don't add a space after directories so that we can continue.
+                let space = if text.ends_with("/") || suffix.as_slice().starts_with(" ") {
+                    ""
+                } else {
+                    " "
+                };
+                let text = format!("{}{}{}", prefix, text, space);
+                self.prompt.set_text_cursor(text.as_slice(), suffix.as_slice());
+            },
+            _ => {
+                let text = format!("{}{}", prefix, candidates.get(0).text);
+                self.prompt.set_text_cursor(text.as_slice(), suffix.as_slice());
+                self.completion = Some(Completion {
+                    prefix: prefix,
+                    suffix: suffix,
+                    candidates: candidates,
+                    index: 0,
+                });
+            },
+        }
+    }
+
     pub fn handle_command_mode_key(&mut self,
                                    key: i32) -> Option<commands::Result> {
+        if key == '\t' as i32 {
+            self.complete();
+            return None;
+        }
+        self.completion = None;
+        let key = match self.search {
+            Some(_) => match self.handle_search_key(key) {
+                Some(key) => key,
+                None => return None,
+            },
+            None => key,
+        };
         if key == '\r' as i32 {
-            let command = commands::parse(self.prompt.text());
+            let command = commands::parse(self.prompt.text(), self.aliases().as_slice());
+            let res = self.command_history.push(self.prompt.text());
+            self.history_saved(res);
             self.prompt.clear();
             self.set_mode(NormalMode);
             Some(command)
@@ -273,11 +1345,68 @@ impl<'a> View<'a> {
             self.set_mode(NormalMode);
             None
         } else {
-            self.prompt.key(key);
+            self.prompt_key(key);
             None
         }
     }
 
+    pub fn handle_insert_mode_key(&mut self,
+                                  key: i32) -> Option<commands::Result> {
+        let chat = match self.chat {
+            Some(chat) => chat,
+            None => {
+                self.set_mode(NormalMode);
+                return None;
+            },
+        };
+        let key = match self.search {
+            Some(_) => match self.handle_search_key(key) {
+                Some(key) => key,
+                None => return None,
+            },
+            None => key,
+        };
+        if key == '\r' as i32 {
+            if self.prompt.text().len() == 0 {
+                return None;
+            }
+            let msg = self.prompt.text().to_string();
+            let res = self.chat_history.push(msg.as_slice());
+            self.history_saved(res);
+            self.prompt.clear();
+            match chat {
+                FriendChat(id) => Some(Ok(Msg(id, msg))),
+                GroupChat(id) => Some(Ok(GroupMsg(id, msg))),
+            }
+        } else if key == 27 {
+            self.close_chat();
+            None
+        } else if key == nc::KEY_PPAGE || key == nc::KEY_NPAGE {
+            let page = if nc::LINES > 6 { nc::LINES as uint / 2 } else { 1 };
+            match self.chat_mut() {
+                Some(c) if key == nc::KEY_PPAGE => c.scroll_up(page),
+                Some(c) => c.scroll_down(page),
+                None => { },
+            }
+            None
+        } else {
+            self.prompt_key(key);
+            None
+        }
+    }
+
+    fn open_chat(&mut self, chat: ChatId) {
+        self.chat = Some(chat);
+        self.prompt.clear();
+        self.set_mode(InsertMode);
+    }
+
+    fn close_chat(&mut self) {
+        self.chat = None;
+        self.prompt.clear();
+        self.set_mode(NormalMode);
+    }
+
     /// Returns a row close to `row` but never `row`.
     fn close(&self, row: Row) -> Row {
         match row {
@@ -286,7 +1415,7 @@ impl<'a> View<'a> {
                     RequestRow(i + 1)
                 } else if i > 0 {
                     RequestRow(i - 1)
-                } else if self.groups > 0 {
+                } else if self.groups.len() > 0 {
                     GroupRow(0)
                 } else if self.friends.len() > 0 {
                     FriendRow(0)
@@ -295,7 +1424,7 @@ impl<'a> View<'a> {
                 }
             },
             GroupRow(i) => {
-                if i + 1 < self.groups {
+                if i + 1 < self.groups.len() {
                     GroupRow(i + 1)
                 } else if i > 0 {
                     GroupRow(i - 1)
@@ -312,8 +1441,8 @@ impl<'a> View<'a> {
                     FriendRow(i + 1)
                 } else if i > 0 {
                     FriendRow(i - 1)
-                } else if self.groups > 0 {
-                    GroupRow(self.groups - 1)
+                } else if self.groups.len() > 0 {
+                    GroupRow(self.groups.len() - 1)
                 } else if self.requests.len() > 0 {
                     RequestRow(self.requests.len() - 1)
                 } else {
@@ -329,47 +1458,132 @@ impl<'a> View<'a> {
             Some(i) => i,
             None => return Err("unknown id"),
         };
+        if self.chat == Some(FriendChat(id)) {
+            self.close_chat();
+        }
+        if self.selected == FriendRow(pos) {
+            self.selected = self.close(self.selected);
+        }
+        self.friends.remove(pos);
         match self.selected {
-            FriendRow(i) if i > pos => self.selected = FriendRow(i-1),
-            FriendRow(i) if i == pos => {
-                self.selected = self.close(self.selected);
-                if self.absolute(self.selected) < self.top {
-                    self.top = self.absolute(self.selected);
-                }
-                return self.del(id);
-            },
+            FriendRow(i) if i > pos => self.selected = FriendRow(i - 1),
             _ => { },
         }
-        self.friends.remove(pos);
+        self.show_selected();
         Ok(())
     }
 
     fn del_selected(&mut self) -> Option<commands::Result> {
         match self.selected {
+            RequestRow(i) => Some(Ok(Decline(self.requests.get(i).number))),
+            GroupRow(i) => Some(Ok(Leave(self.groups.get(i).id))),
             FriendRow(i) => Some(Ok(Del(self.friends.get(i).id))),
             _ => None,
         }
     }
 
+    fn start_search(&mut self) {
+        self.pattern_origin = self.selected;
+        self.pattern.truncate(0);
+        self.prompt.clear();
+        self.set_mode(SearchMode);
+    }
+
+    /// Selects the next row that matches the pattern.
+    fn search_next(&mut self, forward: bool) {
+        match self.find_match(self.selected, forward, false) {
+            Some(row) => {
+                self.selected = row;
+                self.show_selected();
+            },
+            None => { },
+        }
+    }
+
+    /// Selects the next row with unread messages.
+    fn next_unread(&mut self) {
+        match self.find_row(self.selected, true, false, |r| self.has_unread(r)) {
+            Some(row) => {
+                self.selected = row;
+                self.show_selected();
+            },
+            None => { },
+        }
+    }
+
+    pub fn handle_search_mode_key(&mut self, key: i32) -> Option<commands::Result> {
+        if key == '\r' as i32 {
+            self.prompt.clear();
+            self.set_mode(NormalMode);
+            return None;
+        }
+        if key == 27 {
+            self.pattern.truncate(0);
+            self.selected = self.pattern_origin;
+            self.show_selected();
+            self.prompt.clear();
+            self.set_mode(NormalMode);
+            return None;
+        }
+        match self.keymap.edit(key) {
+            // The search pattern has no history.
+            Some(keys::HistoryPrev) | Some(keys::HistoryNext)
+                    | Some(keys::HistorySearch) => return None,
+            _ => { },
+        }
+        self.prompt_key(key);
+        self.pattern = lowercase(self.prompt.text());
+        self.selected = match self.find_match(self.pattern_origin, true, true) {
+            Some(row) => row,
+            None => self.pattern_origin,
+        };
+        self.show_selected();
+        None
+    }
+
     pub fn handle_normal_mode_key(&mut self, key: i32) -> Option<commands::Result> {
-        if key < 128 {
-            match key as u8 as char {
-                'j' => self.down(),
-                'k' => self.up(),
-                // 'a' => self.mode = InsertMode,
-                ':' => self.set_mode(CommandMode),
-                _ => { },
-            }
-        } else {
-            match key {
-                nc::KEY_BACKSPACE => return self.del_selected(),
+        let action = match self.keymap.normal(key) {
+            Some(a) => a,
+            None => return None,
+        };
+        match action {
+            keys::Down => self.down(),
+            keys::Up => self.up(),
+            keys::EnterCommandMode => self.set_mode(CommandMode),
+            keys::Select => match self.selected {
+                RequestRow(i) => return Some(Ok(Accept(self.requests.get(i).number))),
+                GroupRow(i) => {
+                    let id = self.groups.get(i).id;
+                    self.open_chat(GroupChat(id));
+                },
+                FriendRow(i) => {
+                    let id = self.friends.get(i).id;
+                    self.open_chat(FriendChat(id));
+                },
                 _ => { },
-            }
+            },
+            keys::Delete => return self.del_selected(),
+            keys::Search => self.start_search(),
+            keys::SearchNext => self.search_next(true),
+            keys::SearchPrev => self.search_next(false),
+            keys::Undo => return Some(Ok(Undo)),
+            keys::ShowTransfers => self.show_transfers = true,
+            keys::NextUnread => self.next_unread(),
         }
         None
     }
 }
 
+/// Rows other than headers don't borrow anything.
+fn unbound<'a>(row: Row) -> Row<'a> {
+    match row {
+        RequestRow(i) => RequestRow(i),
+        GroupRow(i) => GroupRow(i),
+        FriendRow(i) => FriendRow(i),
+        _ => NoRow,
+    }
+}
+
 struct RowIter<'a> {
     view: &'a View<'a>,
     row: Row<'a>,
@@ -401,7 +1615,7 @@ impl<'a> Iterator<Row<'a>> for RowIter<'a> {
             },
             GroupRow(i) => {
                 if self.header {
-                    if self.view.groups == 0 {
+                    if self.view.groups.len() == 0 {
                         self.row = FriendRow(0);
                         self.next()
                     } else {
@@ -409,7 +1623,7 @@ impl<'a> Iterator<Row<'a>> for RowIter<'a> {
                         Some(Header("Groups"))
                     }
                 } else {
-                    if i < self.view.groups {
+                    if i < self.view.groups.len() {
                         self.row = GroupRow(i+1);
                         Some(GroupRow(i))
                     } else {
@@ -0,0 +1,278 @@
+use libc::{c_int, c_char, c_void, size_t, ssize_t};
+use std::io::{IoResult, IoError, UserDir, UserRead, UserWrite};
+use std::io::fs::{mkdir_recursive, chmod};
+use std::os::{getenv, errno};
+use std::mem::{zeroed, size_of};
+
+#[allow(non_camel_case_types)]
+struct sockaddr_un {
+    sun_family: u16,
+    sun_path: [c_char, ..108],
+}
+
+static AF_UNIX: c_int = 1;
+static SOCK_STREAM: c_int = 1;
+static F_SETFL: c_int = 4;
+#[cfg(target_os = "linux")]
+#[cfg(target_os = "android")]
+static O_NONBLOCK: c_int = 0o4000;
+#[cfg(target_os = "freebsd")]
+#[cfg(target_os = "macos")]
+static O_NONBLOCK: c_int = 4;
+#[cfg(target_os = "linux")]
+#[cfg(target_os = "android")]
+static EAGAIN: int = 11;
+#[cfg(target_os = "freebsd")]
+#[cfg(target_os = "macos")]
+static EAGAIN: int = 35;
+
+extern {
+    fn socket(domain: c_int, ty: c_int, protocol: c_int) -> c_int;
+    fn bind(fd: c_int, addr: *const sockaddr_un, len: u32) -> c_int;
+    fn connect(fd: c_int, addr: *const sockaddr_un, len: u32) -> c_int;
+    fn listen(fd: c_int, backlog: c_int) -> c_int;
+    fn accept(fd: c_int, addr: *mut c_void, len: *mut u32) -> c_int;
+    fn read(fd: c_int, buf: *mut c_void, count: size_t) -> ssize_t;
+    fn write(fd: c_int, buf: *const c_void, count: size_t) -> ssize_t;
+    fn close(fd: c_int) -> c_int;
+    fn unlink(path: *const c_char) -> c_int;
+    fn fcntl(fd: c_int, cmd: c_int, arg: c_int) -> c_int;
+}
+
+/// The longest line a client may send. Clients that send longer ones are
+/// disconnected.
+static MAX_LINE: uint = 64 * 1024;
+
+/// Returns `$XDG_RUNTIME_DIR/stannis/control` or `None` if `$XDG_RUNTIME_DIR` is not
+/// set.
+pub fn default_path() -> Option<Path> {
+    match getenv("XDG_RUNTIME_DIR") {
+        Some(ref s) if s.len() > 0 => {
+            Some(Path::new(s.as_slice()).join_many(["stannis", "control"]))
+        },
+        _ => None,
+    }
+}
+
+fn address(path: &Path) -> IoResult<sockaddr_un> {
+    let mut addr: sockaddr_un = unsafe { zeroed() };
+    let bytes = path.as_vec();
+    if bytes.len() >= addr.sun_path.len() {
+        return Err(IoError::from_errno(36 /* ENAMETOOLONG */, false));
+    }
+    addr.sun_family = AF_UNIX as u16;
+    for (i, &b) in bytes.iter().enumerate() {
+        addr.sun_path[i] = b as c_char;
+    }
+    Ok(addr)
+}
+
+/// Sets `fd` to non-blocking mode.
+fn set_nonblocking(fd: c_int) {
+    unsafe { fcntl(fd, F_SETFL, O_NONBLOCK); }
+}
+
+struct Client {
+    fd: c_int,
+    /// What we've read that isn't a full line yet.
+    input: Vec<u8>,
+    /// Whether the client wants the events.
+    subscribed: bool,
+}
+
+impl Client {
+    /// Takes the complete lines out of the input and adds the commands among them to
+    /// `requests`.
+    fn take_lines(&mut self, requests: &mut Vec<Request>) {
+        loop {
+            let pos = match self.input.iter().position(|&b| b == '\n' as u8) {
+                Some(pos) => pos,
+                None => break,
+            };
+            let line = {
+                let bytes = self.input.slice_to(pos);
+                String::from_utf8_lossy(bytes).into_string()
+            };
+            self.input = self.input.slice_from(pos + 1).to_vec();
+            let line = line.as_slice().trim_right_chars('\r');
+            if line.trim() == "subscribe" {
+                self.subscribed = true;
+            } else if line.trim().len() > 0 {
+                requests.push(CommandLine(self.fd, line.to_string()));
+            }
+        }
+    }
+}
+
+/// What a client sent.
+pub enum Request {
+    /// A line that's parsed like a command typed in command mode.
+    CommandLine(c_int, String),
+    /// A new client has connected. Its file descriptor should be watched.
+    Connected(c_int),
+}
+
+/// A Unix domain socket other programs use to send commands and receive events.
+///
+/// Clients write one command per line. The line `subscribe` makes us send the
+/// client every event as a line of JSON.
+///
+/// Commands don't ask for the confirmation the terminal asks for, e.g. `del` deletes
+/// the friend right away. Each command is answered with the messages it caused and a
+/// `done` event.
+pub struct ControlSocket {
+    fd: c_int,
+    path: Path,
+    clients: Vec<Client>,
+}
+
+impl ControlSocket {
+    /// Listens on `path`. Fails if another instance listens there already.
+    ///
+    /// Only the user may connect. Anyone who can would be able to send messages in
+    /// our name.
+    pub fn bind(path: Path) -> IoResult<ControlSocket> {
+        // The directory is ours, so make sure it's private even if it exists.
+        try!(mkdir_recursive(&path.dir_path(), UserDir));
+        try!(chmod(&path.dir_path(), UserDir));
+        let addr = try!(address(&path));
+        let len = size_of::<sockaddr_un>() as u32;
+        unsafe {
+            if path.exists() {
+                // A socket whose connect failed can't be used for anything else, so
+                // probe with one of its own.
+                let probe = socket(AF_UNIX, SOCK_STREAM, 0);
+                if probe < 0 {
+                    return Err(IoError::last_error());
+                }
+                let listening = connect(probe, &addr, len) == 0;
+                close(probe);
+                if listening {
+                    return Err(IoError::from_errno(98 /* EADDRINUSE */, false));
+                }
+                // Nobody's listening. Left over from a crash.
+                unlink(path.to_c_str().as_ptr());
+            }
+            let fd = socket(AF_UNIX, SOCK_STREAM, 0);
+            if fd < 0 {
+                return Err(IoError::last_error());
+            }
+            if bind(fd, &addr, len) < 0 {
+                let err = IoError::last_error();
+                close(fd);
+                return Err(err);
+            }
+            match chmod(&path, UserRead | UserWrite) {
+                Err(e) => {
+                    close(fd);
+                    unlink(path.to_c_str().as_ptr());
+                    return Err(e);
+                },
+                _ => { },
+            }
+            if listen(fd, 8) < 0 {
+                let err = IoError::last_error();
+                close(fd);
+                unlink(path.to_c_str().as_ptr());
+                return Err(err);
+            }
+            set_nonblocking(fd);
+            Ok(ControlSocket {
+                fd: fd,
+                path: path,
+                clients: Vec::new(),
+            })
+        }
+    }
+
+    pub fn path<'a>(&'a self) -> &'a Path {
+        &self.path
+    }
+
+    /// The file descriptor to watch for new connections.
+    pub fn fd(&self) -> c_int {
+        self.fd
+    }
+
+    /// Accepts new clients and reads what the clients have sent.
+    pub fn receive(&mut self) -> Vec<Request> {
+        let mut requests = Vec::new();
+        loop {
+            let fd = unsafe { accept(self.fd, 0 as *mut c_void, 0 as *mut u32) };
+            if fd < 0 {
+                break;
+            }
+            set_nonblocking(fd);
+            self.clients.push(Client { fd: fd, input: Vec::new(), subscribed: false });
+            requests.push(Connected(fd));
+        }
+        let mut closed = Vec::new();
+        for client in self.clients.mut_iter() {
+            let mut buf = [0u8, ..4096];
+            loop {
+                let n = unsafe {
+                    read(client.fd, buf.as_mut_ptr() as *mut c_void, buf.len() as size_t)
+                };
+                if n > 0 {
+                    client.input.push_all(buf.slice_to(n as uint));
+                    client.take_lines(&mut requests);
+                    if client.input.len() > MAX_LINE {
+                        closed.push(client.fd);
+                        break;
+                    }
+                    continue;
+                }
+                if n == 0 || errno() != EAGAIN {
+                    closed.push(client.fd);
+                }
+                break;
+            }
+        }
+        for &fd in closed.iter() {
+            self.disconnect(fd);
+        }
+        requests
+    }
+
+    fn disconnect(&mut self, fd: c_int) {
+        // Closing the descriptor also removes it from the poll set.
+        unsafe { close(fd); }
+        self.clients.retain(|c| c.fd != fd);
+    }
+
+    /// Sends `line` to the client `fd`. Clients that don't keep up are disconnected.
+    pub fn send(&mut self, fd: c_int, line: &str) {
+        if !self.clients.iter().any(|c| c.fd == fd) {
+            return;
+        }
+        let mut data = line.as_bytes().to_vec();
+        data.push('\n' as u8);
+        let n = unsafe {
+            write(fd, data.as_ptr() as *const c_void, data.len() as size_t)
+        };
+        if n != data.len() as ssize_t {
+            self.disconnect(fd);
+        }
+    }
+
+    /// Sends `line` to all subscribed clients.
+    pub fn publish(&mut self, line: &str) {
+        let fds: Vec<c_int> = self.clients.iter().filter(|c| c.subscribed)
+                                          .map(|c| c.fd).collect();
+        for &fd in fds.iter() {
+            self.send(fd, line);
+        }
+    }
+}
+
+impl Drop for ControlSocket {
+    fn drop(&mut self) {
+        for c in self.clients.iter() {
+            unsafe { close(c.fd); }
+        }
+        unsafe {
+            close(self.fd);
+            unlink(self.path.to_c_str().as_ptr());
+        }
+    }
+}
@@ -0,0 +1,214 @@
+use tox::core::{UserStatus, UserStatusAway, UserStatusBusy};
+use transfers::{State};
+use calls::{CallState};
+use serialize::json::{Json, ToJson};
+use std::collections::{TreeMap};
+
+/// A value of a field in an event.
+pub enum Value<'a> {
+    Str(&'a str),
+    Int(i64),
+    Bool(bool),
+    List(&'a [String]),
+}
+
+impl<'a> ToJson for Value<'a> {
+    fn to_json(&self) -> Json {
+        match *self {
+            Str(s) => s.to_string().to_json(),
+            Int(n) => n.to_json(),
+            Bool(b) => b.to_json(),
+            List(l) => l.to_vec().to_json(),
+        }
+    }
+}
+
+/// Formats an event as a single line of JSON, e.g.
+/// `{"event":"message","friend":3,"text":"hi"}`. The fields are sorted by name.
+pub fn line(kind: &str, fields: &[(&str, Value)]) -> String {
+    let mut obj = TreeMap::new();
+    obj.insert("event".to_string(), kind.to_string().to_json());
+    for &(key, ref val) in fields.iter() {
+        obj.insert(key.to_string(), val.to_json());
+    }
+    obj.to_json().to_string()
+}
+
+fn status_name(status: UserStatus) -> &'static str {
+    match status {
+        UserStatusAway => "away",
+        UserStatusBusy => "busy",
+        _ => "online",
+    }
+}
+
+/// Something that happened. The front end shows it and the control socket sends it to
+/// its clients.
+pub enum Event {
+    /// We're up and running. The address friends add us with.
+    Ready(String),
+    Info(String),
+    Warning(String),
+    Error(String),
+    /// Our address as asked for with `:myid`.
+    Address(String),
+    /// The help asked for with `:help`.
+    HelpText(String),
+    FriendAdded(i32),
+    /// We've sent a friend request to the friend with this address.
+    RequestSent(i32, String),
+    FriendDeleted(i32),
+    NameChanged(i32, String),
+    /// A message from a friend: the friend, their name and the text.
+    Message(i32, String, String),
+    Action(i32, String, String),
+    /// A message we've sent: the friend, the receipt and the text.
+    Sent(i32, u32, String),
+    Delivered(i32, u32),
+    StatusMessageChanged(i32, String),
+    UserStatusChanged(i32, UserStatus),
+    Connection(i32, bool),
+    Typing(i32, bool),
+    /// A friend request: the number `:accept` takes, the client id and the message.
+    Request(uint, String, String),
+    /// The request with this number has been accepted or declined.
+    RequestAnswered(uint),
+    AliasChanged(i32, String),
+    NoteChanged(i32, String),
+    GroupAdded(i32),
+    GroupLeft(i32),
+    /// A friend invites us to a group chat.
+    GroupInvite(i32),
+    /// A message in a group chat: the group, the peer, their name and the text.
+    GroupMessage(i32, i32, String, String),
+    GroupPeers(i32, Vec<String>),
+    /// Our own name, status message or status has changed.
+    SelfChanged(String, String, UserStatus),
+    /// A friend offers a file: the transfer, the friend, the file name and the size.
+    FileOffer(uint, i32, String, u64),
+    TransferChanged(uint, State),
+    CallChanged(i32, Option<CallState>),
+}
+
+impl Event {
+    /// Formats the event as a line of JSON.
+    pub fn line(&self) -> String {
+        match *self {
+            Ready(ref addr) => line("ready", [("address", Str(addr.as_slice()))]),
+            Info(ref s) => line("info", [("message", Str(s.as_slice()))]),
+            Warning(ref s) => line("warning", [("message", Str(s.as_slice()))]),
+            Error(ref s) => line("error", [("message", Str(s.as_slice()))]),
+            Address(ref addr) => line("address", [("address", Str(addr.as_slice()))]),
+            HelpText(ref text) => line("help", [("text", Str(text.as_slice()))]),
+            FriendAdded(id) => line("friend_added", [("friend", Int(id as i64))]),
+            RequestSent(id, ref addr) => {
+                line("request_sent", [("friend", Int(id as i64)),
+                                      ("address", Str(addr.as_slice()))])
+            },
+            FriendDeleted(id) => line("friend_deleted", [("friend", Int(id as i64))]),
+            NameChanged(id, ref name) => {
+                line("name", [("friend", Int(id as i64)), ("name", Str(name.as_slice()))])
+            },
+            Message(id, ref name, ref text) => {
+                line("message", [("friend", Int(id as i64)),
+                                 ("name", Str(name.as_slice())),
+                                 ("text", Str(text.as_slice()))])
+            },
+            Action(id, ref name, ref text) => {
+                line("action", [("friend", Int(id as i64)),
+                                ("name", Str(name.as_slice())),
+                                ("text", Str(text.as_slice()))])
+            },
+            Sent(id, receipt, ref text) => {
+                line("sent", [("friend", Int(id as i64)),
+                              ("receipt", Int(receipt as i64)),
+                              ("text", Str(text.as_slice()))])
+            },
+            Delivered(id, receipt) => {
+                line("delivered", [("friend", Int(id as i64)),
+                                   ("receipt", Int(receipt as i64))])
+            },
+            StatusMessageChanged(id, ref msg) => {
+                line("status_message", [("friend", Int(id as i64)),
+                                        ("message", Str(msg.as_slice()))])
+            },
+            UserStatusChanged(id, status) => {
+                line("status", [("friend", Int(id as i64)),
+                                ("status", Str(status_name(status)))])
+            },
+            Connection(id, online) => {
+                line("connection", [("friend", Int(id as i64)), ("online", Bool(online))])
+            },
+            Typing(id, typing) => {
+                line("typing", [("friend", Int(id as i64)), ("typing", Bool(typing))])
+            },
+            Request(n, ref client_id, ref msg) => {
+                line("request", [("request", Int(n as i64)),
+                                 ("client_id", Str(client_id.as_slice())),
+                                 ("message", Str(msg.as_slice()))])
+            },
+            RequestAnswered(n) => line("request_answered", [("request", Int(n as i64))]),
+            AliasChanged(id, ref alias) => {
+                line("alias", [("friend", Int(id as i64)),
+                               ("alias", Str(alias.as_slice()))])
+            },
+            NoteChanged(id, ref note) => {
+                line("note", [("friend", Int(id as i64)), ("note", Str(note.as_slice()))])
+            },
+            GroupAdded(group) => line("group_added", [("group", Int(group as i64))]),
+            GroupLeft(group) => line("group_left", [("group", Int(group as i64))]),
+            GroupInvite(id) => line("group_invite", [("friend", Int(id as i64))]),
+            GroupMessage(group, peer, ref name, ref text) => {
+                line("group_message", [("group", Int(group as i64)),
+                                       ("peer", Int(peer as i64)),
+                                       ("name", Str(name.as_slice())),
+                                       ("text", Str(text.as_slice()))])
+            },
+            GroupPeers(group, ref peers) => {
+                line("group_peers", [("group", Int(group as i64)),
+                                     ("peers", List(peers.as_slice()))])
+            },
+            SelfChanged(ref name, ref msg, status) => {
+                line("self", [("name", Str(name.as_slice())),
+                              ("status_message", Str(msg.as_slice())),
+                              ("status", Str(status_name(status)))])
+            },
+            FileOffer(id, friend, ref name, size) => {
+                line("file_offer", [("transfer", Int(id as i64)),
+                                    ("friend", Int(friend as i64)),
+                                    ("name", Str(name.as_slice())),
+                                    ("size", Int(size as i64))])
+            },
+            TransferChanged(id, state) => {
+                line("transfer", [("transfer", Int(id as i64)),
+                                  ("state", Str(state.fmt()))])
+            },
+            CallChanged(id, state) => {
+                let state = state.map(|s| s.fmt()).unwrap_or("none");
+                line("call", [("friend", Int(id as i64)), ("state", Str(state))])
+            },
+        }
+    }
+
+    /// Whether the event answers a command. A command from the control socket gets
+    /// these while the other clients don't.
+    pub fn is_reply(&self) -> bool {
+        match *self {
+            Info(_) | Warning(_) | Error(_) | Address(_) | HelpText(_) => true,
+            _ => false,
+        }
+    }
+}
+
+#[test]
+fn test_line() {
+    assert_eq!(line("ready", []).as_slice(), "{\"event\":\"ready\"}");
+    let l = line("message", [("text", Str("a \"b\"\n\\")), ("friend", Int(3)),
+                             ("online", Bool(true))]);
+    assert_eq!(l.as_slice(), "{\"event\":\"message\",\"friend\":3,\"online\":true,\
+                              \"text\":\"a \\\"b\\\"\\n\\\\\"}");
+    let peers = vec!["a".to_string(), "\"b\"".to_string()];
+    let l = line("group_peers", [("peers", List(peers.as_slice()))]);
+    assert_eq!(l.as_slice(), "{\"event\":\"group_peers\",\
+                              \"peers\":[\"a\",\"\\\"b\\\"\"]}");
+}
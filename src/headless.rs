@@ -0,0 +1,58 @@
+use session::{Session, Frontend};
+use events::{Event};
+
+use std::io::{stdout, LineBufferedWriter};
+use std::io::stdio::{stdin_raw, StdReader, StdWriter};
+
+/// Runs the client without a terminal.
+///
+/// Commands are read from stdin, one per line, and events are written to stdout as
+/// lines of JSON. See `events::Event`.
+pub struct Headless {
+    stdin: StdReader,
+    /// What we've read from stdin that isn't a full line yet.
+    input: Vec<u8>,
+    stdout: LineBufferedWriter<StdWriter>,
+}
+
+impl Headless {
+    pub fn new() -> Headless {
+        Headless {
+            stdin: stdin_raw(),
+            input: Vec::new(),
+            stdout: stdout(),
+        }
+    }
+}
+
+impl Frontend for Headless {
+    /// Reads what's available on stdin and runs the complete lines. Stops at the end
+    /// of the input.
+    fn input(&mut self, session: &mut Session) {
+        let mut buf = [0u8, ..4096];
+        match self.stdin.read(buf) {
+            Ok(n) => self.input.push_all(buf.slice_to(n)),
+            Err(_) => {
+                session.shutdown = true;
+                return;
+            },
+        }
+        loop {
+            let pos = match self.input.iter().position(|&b| b == '\n' as u8) {
+                Some(pos) => pos,
+                None => break,
+            };
+            let line = String::from_utf8_lossy(self.input.slice_to(pos)).into_string();
+            self.input = self.input.slice_from(pos + 1).to_vec();
+            session.run_line(line.as_slice().trim_right_chars('\r'), self);
+        }
+    }
+
+    /// Writes `e` to stdout. We stop if nobody reads the events anymore.
+    fn event(&mut self, session: &mut Session, e: &Event) {
+        let res = writeln!(self.stdout, "{}", e.line()).and_then(|_| self.stdout.flush());
+        if res.is_err() {
+            session.shutdown = true;
+        }
+    }
+}
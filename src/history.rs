@@ -0,0 +1,180 @@
+use std::io::{File, BufferedReader, IoResult, Append, Truncate};
+use std::io::fs::{rename};
+use profile::{open_private, create_dir};
+
+/// Number of entries we keep.
+static MAX_ENTRIES: uint = 1000;
+
+/// Lines entered at the prompt.
+pub struct History {
+    entries: Vec<String>,
+    /// The entry we're showing. `entries.len()` if we're not walking through the
+    /// history.
+    pos: uint,
+    /// The text the user was typing before they started walking through the history.
+    draft: String,
+    path: Option<Path>,
+    /// Number of lines in the file.
+    saved: uint,
+}
+
+impl History {
+    /// Creates a history that isn't saved.
+    pub fn new() -> History {
+        History {
+            entries: Vec::new(),
+            pos: 0,
+            draft: String::new(),
+            path: None,
+            saved: 0,
+        }
+    }
+
+    /// Loads the history from `path`. New entries are appended to this file.
+    ///
+    /// The file may contain messages, so only the user may read it.
+    pub fn load(path: Path) -> History {
+        let mut entries = Vec::new();
+        match File::open(&path) {
+            Ok(file) => {
+                for line in BufferedReader::new(file).lines() {
+                    match line {
+                        Ok(l) => entries.push(l.as_slice().trim_right_chars('\n').to_string()),
+                        Err(_) => break,
+                    }
+                }
+            },
+            Err(_) => { },
+        }
+        let saved = entries.len();
+        if entries.len() > MAX_ENTRIES {
+            let excess = entries.len() - MAX_ENTRIES;
+            entries = entries.move_iter().skip(excess).collect();
+        }
+        History {
+            pos: entries.len(),
+            entries: entries,
+            draft: String::new(),
+            path: Some(path),
+            saved: saved,
+        }
+    }
+
+    /// Adds `line` to the end of the history.
+    pub fn push(&mut self, line: &str) -> IoResult<()> {
+        self.reset();
+        if line.len() == 0 || line.contains_char('\n') {
+            return Ok(());
+        }
+        if self.entries.last().map(|l| l.as_slice() == line).unwrap_or(false) {
+            return Ok(());
+        }
+        self.entries.push(line.to_string());
+        if self.entries.len() > MAX_ENTRIES {
+            self.entries.remove(0);
+        }
+        self.pos = self.entries.len();
+        if self.path.is_none() {
+            return Ok(());
+        }
+        // Rewriting the whole file every time would be slow, so we let it grow to
+        // twice the size before we do.
+        if self.saved >= 2 * MAX_ENTRIES {
+            return self.rewrite();
+        }
+        {
+            let path = self.path.as_ref().unwrap();
+            try!(create_dir(&path.dir_path()));
+            let mut file = try!(open_private(path, Append));
+            try!(file.write_line(line));
+        }
+        self.saved += 1;
+        Ok(())
+    }
+
+    /// Replaces the file with the entries we keep so that it doesn't grow forever.
+    fn rewrite(&mut self) -> IoResult<()> {
+        {
+            let path = self.path.as_ref().unwrap();
+            try!(create_dir(&path.dir_path()));
+            let tmp = path.with_extension("tmp");
+            {
+                let mut file = try!(open_private(&tmp, Truncate));
+                for entry in self.entries.iter() {
+                    try!(file.write_line(entry.as_slice()));
+                }
+            }
+            try!(rename(&tmp, path));
+        }
+        self.saved = self.entries.len();
+        Ok(())
+    }
+
+    /// Stops walking through the history.
+    pub fn reset(&mut self) {
+        self.pos = self.entries.len();
+        self.draft.truncate(0);
+    }
+
+    /// Returns the entry before the current one.
+    ///
+    /// `current` is the text in the prompt. It's restored when we walk back to the end
+    /// of the history.
+    pub fn prev<'a>(&'a mut self, current: &str) -> Option<&'a str> {
+        if self.pos == 0 {
+            return None;
+        }
+        if self.pos == self.entries.len() {
+            self.draft = current.to_string();
+        }
+        self.pos -= 1;
+        Some(self.entries.get(self.pos).as_slice())
+    }
+
+    /// Returns the entry after the current one.
+    pub fn next<'a>(&'a mut self) -> Option<&'a str> {
+        if self.pos >= self.entries.len() {
+            return None;
+        }
+        self.pos += 1;
+        if self.pos == self.entries.len() {
+            Some(self.draft.as_slice())
+        } else {
+            Some(self.entries.get(self.pos).as_slice())
+        }
+    }
+
+    /// Returns the index of the last entry before `before` that contains `query`.
+    pub fn search(&self, query: &str, before: uint) -> Option<uint> {
+        let end = if before > self.entries.len() { self.entries.len() } else { before };
+        range(0, end).rev().find(|&i| self.entries.get(i).as_slice().contains(query))
+    }
+
+    pub fn len(&self) -> uint {
+        self.entries.len()
+    }
+
+    pub fn get<'a>(&'a self, i: uint) -> &'a str {
+        self.entries.get(i).as_slice()
+    }
+}
+
+#[test]
+fn test_file_is_trimmed() {
+    use std::io::{TempDir, UserRead, UserWrite};
+    use std::io::fs::{stat};
+
+    let dir = TempDir::new("stannis").unwrap();
+    let path = dir.path().join_many(["new", "history"]);
+    let mut history = History::load(path.clone());
+    for i in range(0, 2 * MAX_ENTRIES + 10) {
+        history.push(i.to_string().as_slice()).unwrap();
+    }
+    assert_eq!(stat(&path).unwrap().perm, UserRead | UserWrite);
+    let history = History::load(path);
+    assert_eq!(history.len(), MAX_ENTRIES);
+    // Compacted to `MAX_ENTRIES` lines at the push that would have made it
+    // `2 * MAX_ENTRIES + 1`.
+    assert_eq!(history.saved, MAX_ENTRIES + 9);
+    assert_eq!(history.get(0), "1010");
+}
@@ -0,0 +1,198 @@
+use nc = ncurses;
+use std::collections::{HashMap};
+
+/// Actions available in normal mode.
+pub enum NormalAction {
+    Down,
+    Up,
+    EnterCommandMode,
+    Select,
+    Delete,
+    Search,
+    SearchNext,
+    SearchPrev,
+    Undo,
+    ShowTransfers,
+    NextUnread,
+}
+
+impl NormalAction {
+    fn from_name(s: &str) -> Option<NormalAction> {
+        let action = match s {
+            "down" => Down,
+            "up" => Up,
+            "command-mode" => EnterCommandMode,
+            "select" => Select,
+            "delete" => Delete,
+            "search" => Search,
+            "search-next" => SearchNext,
+            "search-prev" => SearchPrev,
+            "undo" => Undo,
+            "transfers" => ShowTransfers,
+            "next-unread" => NextUnread,
+            _ => return None,
+        };
+        Some(action)
+    }
+}
+
+/// Actions available while editing the prompt.
+pub enum EditAction {
+    Left,
+    Right,
+    LeftWord,
+    RightWord,
+    Clear,
+    DelBackward,
+    DelForward,
+    DelWord,
+    HistoryPrev,
+    HistoryNext,
+    HistorySearch,
+}
+
+impl EditAction {
+    fn from_name(s: &str) -> Option<EditAction> {
+        let action = match s {
+            "left" => Left,
+            "right" => Right,
+            "left-word" => LeftWord,
+            "right-word" => RightWord,
+            "clear" => Clear,
+            "delete-backward" => DelBackward,
+            "delete-forward" => DelForward,
+            "delete-word" => DelWord,
+            "history-prev" => HistoryPrev,
+            "history-next" => HistoryNext,
+            "history-search" => HistorySearch,
+            _ => return None,
+        };
+        Some(action)
+    }
+}
+
+/// Parses a key description.
+///
+/// Keys are single characters, `c-x` for control characters, or one of the names
+/// below.
+pub fn parse_key(s: &str) -> Option<i32> {
+    let key = match s {
+        "enter" => '\r' as i32,
+        "tab" => '\t' as i32,
+        "space" => ' ' as i32,
+        "backspace" => nc::KEY_BACKSPACE,
+        "up" => nc::KEY_UP,
+        "down" => nc::KEY_DOWN,
+        "left" => nc::KEY_LEFT,
+        "right" => nc::KEY_RIGHT,
+        "s-left" => nc::KEY_SLEFT,
+        "s-right" => nc::KEY_SRIGHT,
+        "pageup" => nc::KEY_PPAGE,
+        "pagedown" => nc::KEY_NPAGE,
+        "home" => nc::KEY_HOME,
+        "end" => nc::KEY_END,
+        "delete" => nc::KEY_DC,
+        _ => {
+            let bytes = s.as_bytes();
+            match bytes.len() {
+                1 if bytes[0] > 0x20 && bytes[0] < 0x7F => bytes[0] as i32,
+                3 if s.starts_with("c-") => match bytes[2] {
+                    b @ 0x40..0x5F => (b - 0x40) as i32,
+                    b @ 0x61..0x7A => (b - 0x60) as i32,
+                    _ => return None,
+                },
+                _ => return None,
+            }
+        },
+    };
+    Some(key)
+}
+
+pub struct Keymap {
+    normal: HashMap<i32, NormalAction>,
+    edit: HashMap<i32, EditAction>,
+}
+
+impl Keymap {
+    /// Creates a keymap with the default bindings.
+    pub fn new() -> Keymap {
+        let mut normal = HashMap::new();
+        normal.insert('j' as i32, Down);
+        normal.insert('k' as i32, Up);
+        normal.insert(nc::KEY_DOWN, Down);
+        normal.insert(nc::KEY_UP, Up);
+        normal.insert(':' as i32, EnterCommandMode);
+        normal.insert('\r' as i32, Select);
+        normal.insert(nc::KEY_BACKSPACE, Delete);
+        normal.insert('/' as i32, Search);
+        normal.insert('n' as i32, SearchNext);
+        normal.insert('N' as i32, SearchPrev);
+        normal.insert('u' as i32, Undo);
+        normal.insert('t' as i32, ShowTransfers);
+        normal.insert('\t' as i32, NextUnread);
+
+        let mut edit = HashMap::new();
+        edit.insert(0x02, Left);        // c-b
+        edit.insert(0x04, Clear);       // c-d
+        edit.insert(0x06, Right);       // c-f
+        edit.insert(0x0E, HistoryNext); // c-n
+        edit.insert(0x10, HistoryPrev); // c-p
+        edit.insert(0x12, HistorySearch); // c-r
+        edit.insert(0x17, DelWord);     // c-w
+        edit.insert(0x18, DelForward);  // c-x
+        edit.insert(nc::KEY_LEFT, Left);
+        edit.insert(nc::KEY_RIGHT, Right);
+        edit.insert(nc::KEY_SLEFT, LeftWord);
+        edit.insert(nc::KEY_SRIGHT, RightWord);
+        edit.insert(nc::KEY_UP, HistoryPrev);
+        edit.insert(nc::KEY_DOWN, HistoryNext);
+        edit.insert(nc::KEY_BACKSPACE, DelBackward);
+
+        Keymap {
+            normal: normal,
+            edit: edit,
+        }
+    }
+
+    pub fn normal(&self, key: i32) -> Option<NormalAction> {
+        self.normal.find(&key).map(|&a| a)
+    }
+
+    pub fn edit(&self, key: i32) -> Option<EditAction> {
+        self.edit.find(&key).map(|&a| a)
+    }
+
+    /// Binds `key` to `action` in normal mode. `none` removes the binding.
+    pub fn bind_normal(&mut self, key: &str, action: &str) -> Result<(), &'static str> {
+        let key = match parse_key(key) {
+            Some(k) => k,
+            None => return Err("invalid key"),
+        };
+        if action == "none" {
+            self.normal.remove(&key);
+            return Ok(());
+        }
+        match NormalAction::from_name(action) {
+            Some(a) => { self.normal.insert(key, a); },
+            None => return Err("unknown action"),
+        }
+        Ok(())
+    }
+
+    /// Binds `key` to `action` in the prompt. `none` removes the binding.
+    pub fn bind_edit(&mut self, key: &str, action: &str) -> Result<(), &'static str> {
+        let key = match parse_key(key) {
+            Some(k) => k,
+            None => return Err("invalid key"),
+        };
+        if action == "none" {
+            self.edit.remove(&key);
+            return Ok(());
+        }
+        match EditAction::from_name(action) {
+            Some(a) => { self.edit.insert(key, a); },
+            None => return Err("unknown action"),
+        }
+        Ok(())
+    }
+}
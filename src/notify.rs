@@ -0,0 +1,55 @@
+use nc = ncurses;
+use term;
+use std::io::process::{Command, Ignored};
+
+/// How we tell the user that something happened while they were looking elsewhere.
+pub struct Notifier {
+    /// Ring the terminal bell.
+    pub bell: bool,
+    /// Ask the terminal to show a notification and mark the window as urgent.
+    pub urgency: bool,
+    /// A shell command that's run with the sender and the message as arguments.
+    pub command: Option<String>,
+}
+
+impl Notifier {
+    pub fn new() -> Notifier {
+        Notifier {
+            bell: false,
+            urgency: false,
+            command: None,
+        }
+    }
+
+    /// Notifies the user about `msg` from `from`.
+    pub fn notify(&self, from: &str, msg: &str) -> Result<(), String> {
+        if self.bell {
+            nc::beep();
+        }
+        if self.urgency {
+            match term::notify(from, msg) {
+                Err(e) => return Err(format!("could not notify the terminal: {}", e)),
+                _ => { },
+            }
+        }
+        match self.command {
+            Some(ref cmd) => {
+                // `$1` and `$2` are the sender and the message.
+                let script = format!("{} \"$@\"", cmd);
+                let res = Command::new("sh").arg("-c").arg(script).arg("stannis")
+                                            .arg(from).arg(msg)
+                                            .stdin(Ignored).stdout(Ignored)
+                                            .stderr(Ignored).spawn();
+                match res {
+                    Ok(mut process) => {
+                        // Reap it when it's done.
+                        spawn(proc() { process.wait().ok(); });
+                    },
+                    Err(e) => return Err(format!("could not run `{}`: {}", cmd, e)),
+                }
+            },
+            None => { },
+        }
+        Ok(())
+    }
+}
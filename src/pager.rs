@@ -0,0 +1,65 @@
+use nc = ncurses;
+use colors::*;
+
+/// Text that's shown instead of the contact list until it's closed.
+pub struct Pager {
+    pub title: String,
+    lines: Vec<String>,
+    /// The first line that's visible.
+    top: uint,
+    /// Number of lines we showed the last time we were drawn.
+    height: uint,
+}
+
+impl Pager {
+    pub fn new(title: String, lines: Vec<String>) -> Pager {
+        Pager {
+            title: title,
+            lines: lines,
+            top: 0,
+            height: 1,
+        }
+    }
+
+    pub fn scroll_up(&mut self, lines: uint) {
+        if lines > self.top {
+            self.top = 0;
+        } else {
+            self.top -= lines;
+        }
+    }
+
+    pub fn scroll_down(&mut self, lines: uint) {
+        self.top += lines;
+    }
+
+    pub fn scroll_to_end(&mut self) {
+        // Corrected when we're drawn.
+        self.top = self.lines.len();
+    }
+
+    /// Number of lines to scroll for a page.
+    pub fn page(&self) -> uint {
+        if self.height > 2 { self.height - 1 } else { 1 }
+    }
+
+    /// Draws the text into the `height` lines starting at `top`.
+    pub fn draw(&mut self, top: i32, height: uint) {
+        self.height = height;
+        if self.top + height > self.lines.len() {
+            self.top = if self.lines.len() > height { self.lines.len() - height } else { 0 };
+        }
+        normal!(COLOR_PAIR_DEFAULT);
+        let mut y = top;
+        for line in self.lines.iter().skip(self.top).take(height) {
+            nc::mvaddstr(y, 0, line.as_slice());
+            nc::clrtoeol();
+            y += 1;
+        }
+        while y < top + height as i32 {
+            nc::move(y, 0);
+            nc::clrtoeol();
+            y += 1;
+        }
+    }
+}
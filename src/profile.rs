@@ -0,0 +1,137 @@
+use tox::core::{Tox};
+use libc::{c_int, c_char};
+use std::os::{getenv};
+use std::io::{File, IoResult, IoError, OtherIoError, TypeFile, UserDir, UserRead,
+              UserWrite, FileMode, Truncate, Write};
+use std::io::fs::{mkdir_recursive, rename, chmod, lstat};
+
+static O_WRONLY: c_int = 1;
+#[cfg(target_os = "linux")]
+#[cfg(target_os = "android")]
+static O_CREAT: c_int = 0o100;
+#[cfg(target_os = "linux")]
+#[cfg(target_os = "android")]
+static O_EXCL: c_int = 0o200;
+#[cfg(target_os = "linux")]
+#[cfg(target_os = "android")]
+static O_NOFOLLOW: c_int = 0o400000;
+#[cfg(target_os = "freebsd")]
+#[cfg(target_os = "macos")]
+static O_CREAT: c_int = 0x200;
+#[cfg(target_os = "freebsd")]
+#[cfg(target_os = "macos")]
+static O_EXCL: c_int = 0x800;
+#[cfg(target_os = "freebsd")]
+#[cfg(target_os = "macos")]
+static O_NOFOLLOW: c_int = 0x100;
+
+extern {
+    fn open(path: *const c_char, flags: c_int, mode: c_int) -> c_int;
+    fn close(fd: c_int) -> c_int;
+}
+
+/// Returns the directory where stannis keeps its data.
+///
+/// This is `$XDG_DATA_HOME/stannis` or `~/.local/share/stannis` if the former is not
+/// set.
+pub fn data_dir() -> Path {
+    let base = match getenv("XDG_DATA_HOME") {
+        Some(ref s) if s.len() > 0 => Path::new(s.as_slice()),
+        _ => {
+            let home = getenv("HOME").unwrap_or(".".to_string());
+            Path::new(home).join_many([".local", "share"])
+        },
+    };
+    base.join("stannis")
+}
+
+/// Returns the default location of the profile.
+pub fn default_path() -> Path {
+    data_dir().join("profile.tox")
+}
+
+/// Creates `dir` if it doesn't exist. Our own data directory is made private even if
+/// it does. Directories the user chose for the profile are left alone.
+pub fn create_dir(dir: &Path) -> IoResult<()> {
+    try!(mkdir_recursive(dir, UserDir));
+    if *dir == data_dir() {
+        try!(chmod(dir, UserDir));
+    }
+    Ok(())
+}
+
+/// Opens `path` for writing. Only the user can read the file.
+///
+/// A missing file is created with these permissions so that it's never readable by
+/// others, not even for a moment. Symlinks and other things that aren't regular
+/// files are refused.
+pub fn open_private(path: &Path, mode: FileMode) -> IoResult<File> {
+    match lstat(path) {
+        Ok(s) if s.kind != TypeFile => {
+            return Err(IoError {
+                kind: OtherIoError,
+                desc: "not a regular file",
+                detail: Some(path.display().to_string()),
+            });
+        },
+        // Written by a version that didn't care.
+        Ok(_) => try!(chmod(path, UserRead | UserWrite)),
+        Err(_) => {
+            let flags = O_WRONLY | O_CREAT | O_EXCL | O_NOFOLLOW;
+            let fd = unsafe { open(path.to_c_str().as_ptr(), flags, 0o600) };
+            if fd < 0 {
+                return Err(IoError::last_error());
+            }
+            unsafe { close(fd); }
+        },
+    }
+    File::open_mode(path, mode, Write)
+}
+
+/// Loads the profile at `path` into `tox`.
+///
+/// A missing profile is not an error. We simply start with a new identity in this
+/// case.
+pub fn load(tox: &Tox, path: &Path) -> Result<(), &'static str> {
+    if !path.exists() {
+        return Ok(());
+    }
+    let data = match File::open(path).read_to_end() {
+        Ok(d) => d,
+        Err(_) => return Err("could not read profile"),
+    };
+    match tox.load(data) {
+        Ok(_) => Ok(()),
+        Err(_) => Err("corrupted profile"),
+    }
+}
+
+/// Saves the profile in `tox` at `path`.
+///
+/// The data is written to a temporary file first so that we don't end up with a
+/// truncated profile if we get killed. The profile contains our secret key so nobody
+/// else may read it.
+pub fn save(tox: &Tox, path: &Path) -> IoResult<()> {
+    try!(create_dir(&path.dir_path()));
+    let tmp = path.with_extension("tmp");
+    {
+        let mut file = try!(open_private(&tmp, Truncate));
+        try!(file.write(tox.save().as_slice()));
+        try!(file.fsync());
+    }
+    rename(&tmp, path)
+}
+
+#[test]
+fn test_open_private() {
+    use std::io::{TempDir};
+    use std::io::fs::{stat, symlink};
+
+    let dir = TempDir::new("stannis").unwrap();
+    let path = dir.path().join("private");
+    open_private(&path, Truncate).unwrap();
+    assert_eq!(stat(&path).unwrap().perm, UserRead | UserWrite);
+    let link = dir.path().join("link");
+    symlink(&path, &link).unwrap();
+    assert!(open_private(&link, Truncate).is_err());
+}
@@ -2,6 +2,8 @@ use colors::*;
 use utfbuf::{UtfBuf};
 use nc = ncurses;
 use term::cwidth::{CharWidth, StringWidth};
+use keys::{EditAction, Left, Right, LeftWord, RightWord, Clear, DelBackward, DelForward,
+           DelWord, HistoryPrev, HistoryNext, HistorySearch};
 
 pub struct Prompt {
     text: String,
@@ -34,6 +36,11 @@ impl Prompt {
         self.text.as_slice()
     }
 
+    /// Returns the position of the cursor in the text.
+    pub fn cursor(&self) -> uint {
+        self.cursor
+    }
+
     pub fn set_prefix(&mut self, prefix: &str) {
         self.prefix = prefix.to_string();
         let old_width = self.prefix_width;
@@ -101,15 +108,6 @@ impl Prompt {
         nc::move(row, self.cursor_term as i32);
     }
 
-    fn high_key(&mut self, key: i32) {
-        match key {
-            nc::KEY_BACKSPACE => self.del(),
-            nc::KEY_LEFT => self.left(),
-            nc::KEY_RIGHT => self.right(),
-            _ => { }
-        };
-    }
-
     fn show_left(&mut self) {
         if self.prefix_width > nc::COLS as uint {
             self.left = self.cursor;
@@ -319,6 +317,23 @@ impl Prompt {
         self.extend_right();
     }
 
+    /// Replaces the text and moves the cursor to the end.
+    pub fn set_text(&mut self, text: &str) {
+        self.clear();
+        for &b in text.as_bytes().iter() {
+            self.low_key(b);
+        }
+    }
+
+    /// Replaces the text with `before` followed by `after` and moves the cursor
+    /// between them.
+    pub fn set_text_cursor(&mut self, before: &str, after: &str) {
+        self.set_text(format!("{}{}", before, after).as_slice());
+        for _ in after.chars() {
+            self.left();
+        }
+    }
+
     pub fn clear(&mut self) {
         self.left = 0;
         self.cursor = 0;
@@ -328,18 +343,18 @@ impl Prompt {
         self.text.truncate(0);
     }
 
-    fn control_key(&mut self, key: u32) {
-        // Keys above 0x20 are unused
-        match key {
-            0x02 => /* c-b */ self.left(),
-            // TODO(mahkoh) replace by c-c later
-            0x04 => /* c-d */ self.clear(),
-            0x06 => /* c-f */ self.right(),
-            0x0E => /* c-n */ self.right_word(),
-            0x10 => /* c-p */ self.left_word(),
-            0x17 => /* c-w */ self.del_word(),
-            0x18 => /* c-x */ self.del_forward(),
-            _ => { }
+    pub fn edit(&mut self, action: EditAction) {
+        match action {
+            Left => self.left(),
+            Right => self.right(),
+            LeftWord => self.left_word(),
+            RightWord => self.right_word(),
+            Clear => self.clear(),
+            DelBackward => self.del(),
+            DelForward => self.del_forward(),
+            DelWord => self.del_word(),
+            // The history is handled by the owner of the prompt.
+            HistoryPrev | HistoryNext | HistorySearch => { },
         }
     }
 
@@ -349,8 +364,8 @@ impl Prompt {
             None => return,
         };
         let val = key as u32;
+        // Control characters have to be bound to an action
         if val < 0x20 || (val >= 0x7F && val < 0xA0) {
-            self.control_key(val);
             return;
         }
         let width = match key.width2() {
@@ -376,10 +391,11 @@ impl Prompt {
         self.trim_right();
     }
 
+    /// Inserts the character `key` is part of.
+    ///
+    /// Keys that are bound to actions should be passed to `edit` instead.
     pub fn key(&mut self, key: i32) {
-        if key as u32 > 0xFF {
-            self.high_key(key);
-        } else {
+        if key as u32 <= 0xFF {
             self.low_key(key as u8);
         }
     }
@@ -0,0 +1,1394 @@
+use signals::{Signals, Pipe, Hangup, Terminate, WinSize};
+use tox::core::{Tox, Address, FaerrToolong, FaerrOwnkey, FaerrAlreadysent,
+                FaerrBadchecksum, NameChange, FriendMessage, FriendAction, FriendRequest,
+                ClientId, GroupInvite, GroupMessage, GroupNamelistChange, UserStatusVar,
+                ConnectionStatusVar, Online, Offline, TypingChange, ReadReceipt,
+                FileSendRequest, FileControl, FileData, TransferType, Sending, Receiving,
+                ControlAccept, ControlPause, ControlKill, ControlFinished};
+use tox::av::{Av, AvEvent, AvInvite, AvStart, AvCancel, AvReject, AvEnd,
+              AvRequestTimeout, AvPeerTimeout, AvAudio};
+use tox;
+use fdpoll::{FDPoll, Read};
+use commands::{Command, Quit, Add, Del, Undo, Msg, Alias, Note, Mute, Accept, Decline,
+               GroupCreate, Invite, Join, Leave, GroupMsg, MyId, ToStatus, ToFile,
+               ToClipboard, SetName, SetStatusMessage, SetUserStatus, Source, Help,
+               Messages, SendFile, ShowTransfers, FileAction, FileAccept, FilePause,
+               FileResume, FileCancel, Call, AnswerCall, EndCall};
+use commands;
+use profile;
+use config;
+use config::{Config};
+use args::{Options};
+use addressbook::{AddressBook};
+use transfers;
+use transfers::{Transfers, IoRequest, IoEvent, Direction, Upload, Download, State,
+                Offered, Active, Paused, Done, Cancelled, Failed};
+use calls;
+use calls::{Calls, MediaBackend, NullBackend, FileBackend, Calling, Ringing, InCall};
+use control;
+use control::{ControlSocket, CommandLine, Connected};
+use events;
+use events::{Event};
+use headless::{Headless};
+use ui::{Ui};
+
+use libc::{c_int, c_short, c_ulong};
+use std::mem;
+use std::rc::{Rc};
+use std::comm::{Select};
+use std::collections::{HashMap};
+use std::io::{stderr, File, UserDir};
+use std::io::fs::{stat, mkdir_recursive};
+use std::io::timer::{Timer};
+use std::os::{set_exit_status};
+
+#[allow(non_camel_case_types)]
+struct pollfd {
+    fd: c_int,
+    events: c_short,
+    revents: c_short,
+}
+
+static POLLIN: c_short = 1;
+
+extern {
+    fn poll(fds: *mut pollfd, nfds: c_ulong, timeout: c_int) -> c_int;
+}
+
+/// Returns whether `fd` can be read from without blocking.
+fn readable(fd: c_int) -> bool {
+    let mut p = pollfd { fd: fd, events: POLLIN, revents: 0 };
+    unsafe { poll(&mut p as *mut pollfd, 1, 0) > 0 && p.revents != 0 }
+}
+
+/// Bootstraps from all configured nodes and returns a message if any of them failed.
+fn bootstrap(tox: &Tox, config: &Config, ipv6: bool) -> Option<String> {
+    let nodes = config.bootstrap_nodes();
+    if nodes.len() == 0 {
+        return Some("no bootstrap nodes configured".to_string());
+    }
+    let mut failed = Vec::new();
+    for node in nodes.iter() {
+        let res = tox.bootstrap_from_address(node.host.clone(), ipv6, node.port,
+                                             box node.key.clone());
+        if res.is_err() {
+            failed.push(format!("{}:{}", node.host, node.port));
+        }
+    }
+    match failed.len() {
+        0 => None,
+        n => Some(format!("could not bootstrap from {} of {} nodes: {}", n, nodes.len(),
+                          failed.connect(", "))),
+    }
+}
+
+pub fn run(opts: Options) {
+    let Options { profile, config: config_path, nodes, ipv6, print_address,
+                  headless } = opts;
+    let mut config = match config::load(&config_path) {
+        Ok(c) => c,
+        Err(s) => {
+            (writeln!(stderr(), "{}", s)).ok();
+            set_exit_status(1);
+            return;
+        },
+    };
+    if nodes.len() > 0 {
+        config.nodes = nodes;
+        config.public_nodes = false;
+    }
+    let tox = Tox::new(ipv6).unwrap();
+    // Don't start if we can't load the profile. We'd overwrite it with a fresh
+    // identity when we shut down.
+    match profile::load(&tox, &profile) {
+        Err(s) => {
+            (writeln!(stderr(), "{}: {}", profile.display(), s)).ok();
+            set_exit_status(1);
+            return;
+        },
+        _ => { },
+    }
+    if print_address {
+        // Save the profile so that a freshly created identity sticks.
+        match profile::save(&tox, &profile) {
+            Ok(_) => println!("{}", tox.get_address()),
+            Err(e) => {
+                (writeln!(stderr(), "could not save {}: {}", profile.display(), e)).ok();
+                set_exit_status(1);
+            },
+        }
+        return;
+    }
+    let addressbook = match AddressBook::load(profile.dir_path().join("addressbook")) {
+        Ok(a) => a,
+        Err(e) => {
+            (writeln!(stderr(), "could not read the address book: {}", e)).ok();
+            set_exit_status(1);
+            return;
+        },
+    };
+    let warning = bootstrap(&tox, &config, ipv6);
+    let mut session = Session::new(Rc::new(tox), profile.clone(), addressbook, &config);
+    match warning {
+        Some(s) => session.emit(events::Warning(s)),
+        None => { },
+    }
+    session.open_control();
+    if headless {
+        session.run(&mut Headless::new());
+    } else {
+        let mut ui = Ui::new(&mut session, config_path, config);
+        session.run(&mut ui);
+        ui.close();
+    }
+
+    match profile::save(&*session.tox, &profile) {
+        Err(e) => {
+            (writeln!(stderr(), "could not save {}: {}", profile.display(), e)).ok();
+            set_exit_status(1);
+        },
+        _ => { },
+    }
+}
+
+/// How long a deleted friend can be restored in seconds.
+static UNDO_TIMEOUT: uint = 10;
+
+/// Number of calls toxav keeps track of at the same time.
+static MAX_CALLS: i32 = 4;
+/// How long a friend's client rings before the call is given up in seconds.
+static RING_TIMEOUT: i32 = 30;
+
+/// Returns the media backend `config` asks for.
+fn media_backend(config: &Config) -> Box<MediaBackend> {
+    match (&config.call_input, &config.call_output) {
+        (&None, &None) => box NullBackend as Box<MediaBackend>,
+        (input, output) => {
+            box FileBackend::new(input.clone(), output.clone()) as Box<MediaBackend>
+        },
+    }
+}
+
+/// Returns how tox refers to transfers in `direction`.
+fn transfer_type(direction: Direction) -> TransferType {
+    match direction {
+        Upload => Sending,
+        Download => Receiving,
+    }
+}
+
+fn direction(ty: TransferType) -> Direction {
+    match ty {
+        Sending => Upload,
+        Receiving => Download,
+    }
+}
+
+/// Returns `path` or, if that exists, `path` with a number appended.
+fn unique_path(path: Path) -> Path {
+    if !path.exists() {
+        return path;
+    }
+    let name = path.filename_str().unwrap_or("unnamed").to_string();
+    for i in range(1u, 1000) {
+        let p = path.with_filename(format!("{}.{}", name, i));
+        if !p.exists() {
+            return p;
+        }
+    }
+    path
+}
+
+/// A friend we've deleted and can still add again.
+struct Deleted {
+    id: ClientId,
+    name: String,
+    /// Seconds until we forget about the friend.
+    timeout: uint,
+}
+
+/// What the user sees and types into: the terminal or stdin and stdout.
+pub trait Frontend {
+    /// Reads and handles the user's input. Called when stdin is readable.
+    fn input(&mut self, session: &mut Session);
+
+    /// Shows `e` to the user.
+    fn event(&mut self, session: &mut Session, e: &Event);
+
+    /// Runs a command of a script if only the front end can run it. Returns the
+    /// commands the session runs.
+    fn command(&mut self, _: &mut Session, c: Command) -> Option<Command> {
+        Some(c)
+    }
+
+    /// The friend that commands like `:call` refer to if they don't name one.
+    fn current_friend(&self) -> Option<i32> {
+        None
+    }
+
+    /// Called every second.
+    fn tick(&mut self, _: &Session) { }
+
+    /// Called when the terminal has changed its size.
+    fn resize(&mut self) { }
+
+    /// Called before we wait for something to happen.
+    fn update(&mut self, _: &Session) { }
+}
+
+/// Tox and everything we do with it. The front ends and the control socket all go
+/// through this and are told what happened with events.
+pub struct Session {
+    pub tox: Rc<Tox>,
+    pub profile: Path,
+    pub addressbook: AddressBook,
+    /// Friend requests by the number they're accepted with. Answered requests are
+    /// `None` so that the numbers don't change.
+    requests: Vec<Option<ClientId>>,
+    /// The last group chat each friend invited us to.
+    invites: HashMap<i32, ClientId>,
+    deleted: Option<Deleted>,
+    pub transfers: Transfers,
+    io: Sender<IoRequest>,
+    io_events: Option<Receiver<IoEvent>>,
+    download_dir: Path,
+    /// `None` if toxav couldn't be started. Calls are turned off then.
+    av: Option<Rc<Av>>,
+    calls: Calls,
+    /// Turns the clock that tells us when to send audio on and off.
+    clock: Sender<bool>,
+    clock_ticks: Option<Receiver<()>>,
+    /// The friend we've told that we're typing.
+    typing: Option<i32>,
+    control: Option<ControlSocket>,
+    /// The control socket client whose command we're running. Replies go to it.
+    reply_to: Option<c_int>,
+    /// What happened since the front end was last told.
+    events: Vec<Event>,
+    pub shutdown: bool,
+}
+
+impl Session {
+    pub fn new(tox: Rc<Tox>, profile: Path, addressbook: AddressBook,
+               config: &Config) -> Session {
+        let (io, io_events) = transfers::spawn_io();
+        let (clock, clock_ticks) = calls::spawn_clock();
+        let av = match Av::new(&*tox, MAX_CALLS) {
+            Ok(av) => Some(Rc::new(av)),
+            Err(_) => None,
+        };
+        let mut session = Session {
+            tox: tox,
+            profile: profile,
+            addressbook: addressbook,
+            requests: Vec::new(),
+            invites: HashMap::new(),
+            deleted: None,
+            transfers: Transfers::new(),
+            io: io,
+            io_events: Some(io_events),
+            download_dir: config.download_dir.clone(),
+            av: av,
+            calls: Calls::new(media_backend(config)),
+            clock: clock,
+            clock_ticks: Some(clock_ticks),
+            typing: None,
+            control: None,
+            reply_to: None,
+            events: Vec::new(),
+            shutdown: false,
+        };
+        if session.av.is_none() {
+            session.warning("could not start toxav, calls are turned off".to_string());
+        }
+        session
+    }
+
+    /// Applies the parts of `config` that aren't about the terminal.
+    pub fn configure(&mut self, config: &Config) {
+        self.calls.set_backend(media_backend(config));
+        self.download_dir = config.download_dir.clone();
+    }
+
+    /// Listens on the control socket if we can.
+    pub fn open_control(&mut self) {
+        let path = match control::default_path() {
+            Some(p) => p,
+            None => return,
+        };
+        match ControlSocket::bind(path.clone()) {
+            Ok(c) => self.control = Some(c),
+            Err(e) => {
+                self.warning(format!("no control socket at {}: {}", path.display(), e));
+            },
+        }
+    }
+
+    pub fn emit(&mut self, e: Event) {
+        self.events.push(e);
+    }
+
+    fn info(&mut self, msg: String) {
+        self.emit(events::Info(msg));
+    }
+
+    fn warning(&mut self, msg: String) {
+        self.emit(events::Warning(msg));
+    }
+
+    fn error(&mut self, msg: String) {
+        self.emit(events::Error(msg));
+    }
+
+    pub fn run<F: Frontend>(&mut self, frontend: &mut F) {
+        let sigs = Signals::new().unwrap();
+        sigs.subscribe(Hangup);
+        sigs.subscribe(Terminate);
+        sigs.subscribe(Pipe);
+        sigs.subscribe(WinSize);
+
+        let fdpoll = FDPoll::new(10).unwrap();
+        fdpoll.add(0, Read).unwrap();
+        match self.control {
+            Some(ref c) => fdpoll.add(c.fd(), Read).unwrap(),
+            None => { },
+        }
+        fdpoll.wait().unwrap();
+
+        let select = Select::new();
+        let mut fdpoll_hdl = select.handle(&fdpoll.rcv);
+        unsafe { fdpoll_hdl.add(); }
+        let mut sig_hdl = select.handle(sigs.receiver());
+        unsafe { sig_hdl.add(); }
+        let tox = self.tox.clone();
+        let mut tox_hdl = select.handle(&tox.events);
+        unsafe { tox_hdl.add(); }
+        let mut timer = Timer::new().unwrap();
+        let tick = timer.periodic(1000);
+        let mut tick_hdl = select.handle(&tick);
+        unsafe { tick_hdl.add(); }
+        let io_events = self.io_events.take().unwrap();
+        let mut io_hdl = select.handle(&io_events);
+        unsafe { io_hdl.add(); }
+        let av = self.av.clone();
+        let mut av_hdl = av.as_ref().map(|av| select.handle(&av.events));
+        match av_hdl {
+            Some(ref mut h) => unsafe { h.add(); },
+            None => { },
+        }
+        let clock_ticks = self.clock_ticks.take().unwrap();
+        let mut clock_hdl = select.handle(&clock_ticks);
+        unsafe { clock_hdl.add(); }
+
+        let addr = self.tox.get_address().to_string();
+        self.emit(events::Ready(addr));
+
+        loop {
+            self.dispatch(frontend);
+            frontend.update(self);
+
+            let r = select.wait();
+
+            if r == tox_hdl.id() {
+                for e in tox.events() {
+                    self.tox_event(e);
+                }
+            }
+
+            if r == fdpoll_hdl.id() {
+                fdpoll.rcv.recv().ok();
+                if readable(0) {
+                    frontend.input(self);
+                }
+                self.control_requests(&fdpoll, frontend);
+                fdpoll.wait().ok();
+            }
+
+            if r == io_hdl.id() {
+                loop {
+                    match io_events.try_recv() {
+                        Ok(e) => self.io_event(e),
+                        Err(_) => break,
+                    }
+                }
+            }
+
+            if av_hdl.as_ref().map(|h| h.id()) == Some(r) {
+                let av = av.as_ref().unwrap();
+                for e in av.events() {
+                    self.av_event(&**av, e);
+                }
+            }
+
+            if r == clock_hdl.id() {
+                while clock_ticks.try_recv().is_ok() { }
+                self.send_audio();
+            }
+
+            if r == tick_hdl.id() {
+                tick.recv();
+                self.tick();
+                frontend.tick(self);
+            }
+
+            if r == sig_hdl.id() {
+                for s in sigs.iter() {
+                    match s {
+                        Hangup | Terminate => self.shutdown = true,
+                        WinSize => frontend.resize(),
+                        _ => { /* ignore sigpipe */ },
+                    }
+                }
+            }
+
+            if self.shutdown {
+                break;
+            }
+        }
+
+        self.dispatch(frontend);
+        fdpoll.abort().ok();
+        self.control = None;
+    }
+
+    /// Tells the front end and the clients of the control socket what happened.
+    /// Replies only go to the client whose command we're running.
+    fn dispatch<F: Frontend>(&mut self, frontend: &mut F) {
+        while self.events.len() > 0 {
+            let events = mem::replace(&mut self.events, Vec::new());
+            for e in events.iter() {
+                let reply_to = if e.is_reply() { self.reply_to } else { None };
+                match (reply_to, &mut self.control) {
+                    (Some(fd), &Some(ref mut c)) => c.send(fd, e.line().as_slice()),
+                    (None, &Some(ref mut c)) => c.publish(e.line().as_slice()),
+                    _ => { },
+                }
+                frontend.event(self, e);
+            }
+        }
+    }
+
+    /// Runs the commands clients of the control socket have sent.
+    fn control_requests<F: Frontend>(&mut self, fdpoll: &FDPoll, frontend: &mut F) {
+        let requests = match self.control {
+            Some(ref mut c) => c.receive(),
+            None => return,
+        };
+        for r in requests.move_iter() {
+            match r {
+                Connected(fd) => { fdpoll.add(fd, Read).ok(); },
+                CommandLine(fd, line) => {
+                    self.reply_to = Some(fd);
+                    self.run_line(line.as_slice(), frontend);
+                    self.dispatch(frontend);
+                    self.reply_to = None;
+                    let done = events::line("done", []);
+                    match self.control {
+                        Some(ref mut c) => c.send(fd, done.as_slice()),
+                        None => { },
+                    }
+                },
+            }
+        }
+    }
+
+    /// Returns the ids and aliases of the friends that have one.
+    pub fn aliases(&self) -> Vec<(i32, String)> {
+        let mut aliases = Vec::new();
+        for &id in self.tox.get_friendlist().iter() {
+            let client_id = match self.tox.get_client_id(id) {
+                Some(c) => c,
+                None => continue,
+            };
+            match self.addressbook.get(&*client_id) {
+                Some(c) if c.alias.len() > 0 => aliases.push((id, c.alias.clone())),
+                _ => { },
+            }
+        }
+        aliases
+    }
+
+    /// Runs a command line of a script, i.e. from stdin in headless mode or from the
+    /// control socket. Commands that ask for confirmation on the terminal don't ask.
+    pub fn run_line<F: Frontend>(&mut self, line: &str, frontend: &mut F) {
+        // Commands may be written like in command mode.
+        let line = line.trim_left_chars(':');
+        if line.trim().len() == 0 {
+            return;
+        }
+        let aliases = self.aliases();
+        match commands::parse(line, aliases.as_slice()) {
+            Ok(c) => match frontend.command(self, c) {
+                Some(c) => self.command(c, frontend.current_friend()),
+                None => { },
+            },
+            Err(e) => self.error(e),
+        }
+    }
+
+    /// Runs `c`. `current` is the friend the front end has selected.
+    pub fn command(&mut self, c: Command, current: Option<i32>) {
+        match c {
+            Quit => self.shutdown = true,
+            Add(addr, msg) => self.add(addr, msg),
+            Del(id) => self.del(id),
+            Undo => self.undo(),
+            Msg(id, msg) => self.send(id, msg),
+            Alias(id, alias) => self.set_alias(id, alias),
+            Note(id, note) => self.set_note(id, note),
+            Mute(id, muted) => self.set_muted(id, muted),
+            Accept(n) => self.accept(n),
+            Decline(n) => self.decline(n),
+            GroupCreate => {
+                match self.tox.add_groupchat() {
+                    Ok(group) => self.emit(events::GroupAdded(group)),
+                    Err(_) => self.error("could not create group chat".to_string()),
+                }
+            },
+            Invite(friend, group) => {
+                if self.tox.invite_friend(friend, group).is_err() {
+                    let name = self.friend_name(friend);
+                    self.error(format!("could not invite {} to group {}", name, group));
+                }
+            },
+            Join(friend) => self.join(friend),
+            Leave(group) => {
+                match self.tox.del_groupchat(group) {
+                    Ok(_) => self.emit(events::GroupLeft(group)),
+                    Err(_) => self.error(format!("unknown group {}", group)),
+                }
+            },
+            GroupMsg(group, msg) => {
+                if self.tox.group_message_send(group, msg).is_err() {
+                    self.error(format!("could not send message to group {}", group));
+                }
+            },
+            MyId(ToStatus) => {
+                let addr = self.tox.get_address().to_string();
+                self.emit(events::Address(addr));
+            },
+            MyId(ToFile(path)) => {
+                let addr = self.tox.get_address().to_string();
+                let res = File::create(&path).and_then(|mut f| {
+                    f.write_line(addr.as_slice())
+                });
+                let path = path.display();
+                match res {
+                    Ok(_) => self.info(format!("address written to {}", path)),
+                    Err(e) => self.error(format!("could not write {}: {}", path, e)),
+                }
+            },
+            SetName(name) => {
+                let res = self.tox.set_name(name);
+                self.self_changed(res, "could not set name");
+            },
+            SetStatusMessage(msg) => {
+                let res = self.tox.set_status_message(msg);
+                self.self_changed(res, "could not set status message");
+            },
+            SetUserStatus(status) => {
+                let res = self.tox.set_user_status(status);
+                self.self_changed(res, "could not set status");
+            },
+            Help(topic) => {
+                match commands::help(topic.as_ref().map(|t| t.as_slice())) {
+                    Ok(lines) => self.emit(events::HelpText(lines.connect("\n"))),
+                    Err(e) => self.error(e),
+                }
+            },
+            SendFile(path) => self.send_file(path, current),
+            commands::File(action, id) => self.file_control(action, id),
+            Call(friend) => self.call(friend.or(current)),
+            AnswerCall => self.answer_call(),
+            EndCall => self.hang_up(),
+            MyId(ToClipboard) | Source(_) | Messages | ShowTransfers => {
+                self.error("not available without the terminal".to_string());
+            },
+        }
+    }
+
+    /// Returns the alias or the name of the friend `id`.
+    fn display_name(&self, id: i32) -> String {
+        let alias = match self.tox.get_client_id(id) {
+            Some(c) => self.addressbook.get(&*c).map(|c| c.alias.clone()),
+            None => None,
+        };
+        match alias {
+            Some(a) if a.len() > 0 => a,
+            _ => match self.tox.get_name(id) {
+                Some(name) if name.len() > 0 => name,
+                _ => "anonymous".to_string(),
+            },
+        }
+    }
+
+    /// Returns the name of the friend `id` for messages.
+    fn friend_name(&self, id: i32) -> String {
+        format!("{} ({})", self.display_name(id), id)
+    }
+
+    pub fn muted(&self, id: i32) -> bool {
+        match self.tox.get_client_id(id) {
+            Some(c) => self.addressbook.get(&*c).map(|c| c.muted).unwrap_or(false),
+            None => false,
+        }
+    }
+
+    /// Tells the friend `friend` that we're typing to them and the one before that
+    /// that we've stopped.
+    pub fn set_typing(&mut self, friend: Option<i32>) {
+        if friend == self.typing {
+            return;
+        }
+        match self.typing {
+            Some(id) => { self.tox.set_user_is_typing(id, false).ok(); },
+            None => { },
+        }
+        match friend {
+            Some(id) => { self.tox.set_user_is_typing(id, true).ok(); },
+            None => { },
+        }
+        self.typing = friend;
+    }
+
+    fn add(&mut self, addr: Address, msg: String) {
+        let addr_str = addr.to_string();
+        match self.tox.add_friend(box addr, msg) {
+            Ok(id) => {
+                // Kept so that `:add` can complete it if we need to add them again.
+                match self.tox.get_client_id(id) {
+                    Some(c) => {
+                        self.addressbook.set_address(&*c, addr_str.clone());
+                        self.save_addressbook();
+                    },
+                    None => { },
+                }
+                self.emit(events::FriendAdded(id));
+                self.emit(events::RequestSent(id, addr_str));
+            },
+            Err(FaerrToolong) => self.error("message too long".to_string()),
+            Err(FaerrOwnkey) => self.error("own key".to_string()),
+            Err(FaerrAlreadysent) => self.error("already sent".to_string()),
+            Err(FaerrBadchecksum) => self.error("bad checksum".to_string()),
+            Err(_) => self.error("unknown error".to_string()),
+        }
+    }
+
+    fn del(&mut self, id: i32) {
+        if !self.tox.get_friendlist().contains(&id) {
+            self.error(format!("unknown id {}", id));
+            return;
+        }
+        let client_id = self.tox.get_client_id(id);
+        let tox_name = self.tox.get_name(id).unwrap_or(String::new());
+        let name = self.friend_name(id);
+        if self.tox.del_friend(id).is_err() {
+            self.error(format!("could not delete {}", name));
+            return;
+        }
+        self.emit(events::FriendDeleted(id));
+        match client_id {
+            Some(client_id) => {
+                self.deleted = Some(Deleted {
+                    id: *client_id,
+                    name: tox_name,
+                    timeout: UNDO_TIMEOUT,
+                });
+                self.info(format!("deleted {}, :undo to add them again", name));
+            },
+            None => self.info(format!("deleted {}", name)),
+        }
+    }
+
+    /// Adds the friend we deleted last again.
+    fn undo(&mut self) {
+        let deleted = match self.deleted.take() {
+            Some(d) => d,
+            None => {
+                self.error("nothing to undo".to_string());
+                return;
+            },
+        };
+        match self.tox.add_friend_norequest(box deleted.id) {
+            Ok(friend) => {
+                self.emit(events::FriendAdded(friend));
+                if deleted.name.len() > 0 {
+                    self.emit(events::NameChanged(friend, deleted.name));
+                }
+                let name = self.friend_name(friend);
+                self.info(format!("added {} again", name));
+            },
+            Err(_) => self.error("could not add the friend again".to_string()),
+        }
+    }
+
+    fn send(&mut self, id: i32, msg: String) {
+        match self.tox.send_message(id, msg.clone()) {
+            Ok(receipt) => self.emit(events::Sent(id, receipt, msg)),
+            Err(_) => {
+                let name = self.friend_name(id);
+                self.error(format!("could not send message to {}", name));
+            },
+        }
+    }
+
+    fn set_alias(&mut self, id: i32, alias: String) {
+        let client_id = match self.tox.get_client_id(id) {
+            Some(c) => c,
+            None => {
+                self.error(format!("unknown id {}", id));
+                return;
+            },
+        };
+        self.addressbook.set_alias(&*client_id, alias.clone());
+        self.save_addressbook();
+        self.emit(events::AliasChanged(id, alias));
+    }
+
+    fn set_note(&mut self, id: i32, note: String) {
+        let client_id = match self.tox.get_client_id(id) {
+            Some(c) => c,
+            None => {
+                self.error(format!("unknown id {}", id));
+                return;
+            },
+        };
+        self.addressbook.set_note(&*client_id, note.clone());
+        self.save_addressbook();
+        self.emit(events::NoteChanged(id, note));
+    }
+
+    fn set_muted(&mut self, id: i32, muted: bool) {
+        let client_id = match self.tox.get_client_id(id) {
+            Some(c) => c,
+            None => {
+                self.error(format!("unknown id {}", id));
+                return;
+            },
+        };
+        self.addressbook.set_muted(&*client_id, muted);
+        self.save_addressbook();
+        let name = self.friend_name(id);
+        let what = if muted { "muted" } else { "unmuted" };
+        self.info(format!("{} {}", what, name));
+    }
+
+    fn save_addressbook(&mut self) {
+        match self.addressbook.save() {
+            Err(e) => {
+                let path = self.addressbook.path().display().to_string();
+                self.error(format!("could not save {}: {}", path, e));
+            },
+            _ => { },
+        }
+    }
+
+    fn accept(&mut self, n: uint) {
+        let id = match self.requests.as_slice().get(n) {
+            Some(&Some(ref id)) => id.clone(),
+            _ => {
+                self.error(format!("unknown request {}", n));
+                return;
+            },
+        };
+        match self.tox.add_friend_norequest(box id) {
+            Ok(friend) => {
+                *self.requests.get_mut(n) = None;
+                self.emit(events::RequestAnswered(n));
+                self.emit(events::FriendAdded(friend));
+            },
+            Err(_) => self.error("could not accept request".to_string()),
+        }
+    }
+
+    fn decline(&mut self, n: uint) {
+        let known = match self.requests.as_mut_slice().get_mut(n) {
+            Some(r) => r.take().is_some(),
+            None => false,
+        };
+        if known {
+            self.emit(events::RequestAnswered(n));
+        } else {
+            self.error(format!("unknown request {}", n));
+        }
+    }
+
+    fn join(&mut self, friend: i32) {
+        let key = match self.invites.pop(&friend) {
+            Some(key) => key,
+            None => {
+                self.error("no pending invitation".to_string());
+                return;
+            },
+        };
+        match self.tox.join_groupchat(friend, box key) {
+            Ok(group) => self.emit(events::GroupAdded(group)),
+            Err(_) => self.error("could not join group chat".to_string()),
+        }
+    }
+
+    fn self_changed(&mut self, res: Result<(), ()>, err: &str) {
+        if res.is_err() {
+            self.error(err.to_string());
+            return;
+        }
+        let name = self.tox.get_self_name().unwrap_or(String::new());
+        let status = self.tox.get_self_status_message().unwrap_or(String::new());
+        let user_status = self.tox.get_self_user_status();
+        self.emit(events::SelfChanged(name, status, user_status));
+        match profile::save(&*self.tox, &self.profile) {
+            Err(e) => self.error(format!("could not save profile: {}", e)),
+            _ => { },
+        }
+    }
+
+    /// Called every second.
+    fn tick(&mut self) {
+        self.transfers.tick();
+        let expired = match self.deleted {
+            Some(ref mut d) => {
+                d.timeout -= 1;
+                d.timeout == 0
+            },
+            None => false,
+        };
+        if expired {
+            self.deleted = None;
+        }
+    }
+
+    fn tox_event(&mut self, e: tox::core::Event) {
+        match e {
+            NameChange(i, name) => self.emit(events::NameChanged(i, name)),
+            FriendMessage(i, msg) => {
+                let name = self.display_name(i);
+                self.emit(events::Message(i, name, msg));
+            },
+            FriendAction(i, msg) => {
+                let name = self.display_name(i);
+                self.emit(events::Action(i, name, msg));
+            },
+            FriendRequest(id, msg) => {
+                let client_id = id.to_string();
+                self.requests.push(Some(*id));
+                let n = self.requests.len() - 1;
+                self.emit(events::Request(n, client_id, msg));
+            },
+            GroupInvite(i, group) => {
+                self.invites.insert(i, *group);
+                self.emit(events::GroupInvite(i));
+            },
+            GroupMessage(group, peer, msg) => {
+                let name = match self.tox.group_peername(group, peer) {
+                    Some(ref name) if name.len() > 0 => name.clone(),
+                    _ => "anonymous".to_string(),
+                };
+                self.emit(events::GroupMessage(group, peer, name, msg));
+            },
+            GroupNamelistChange(group, _, _) => {
+                match self.tox.group_get_names(group) {
+                    Some(names) => self.emit(events::GroupPeers(group, names)),
+                    None => { },
+                }
+            },
+            tox::core::StatusMessage(i, msg) => {
+                self.emit(events::StatusMessageChanged(i, msg));
+            },
+            UserStatusVar(i, status) => self.emit(events::UserStatusChanged(i, status)),
+            ConnectionStatusVar(i, Online) => self.emit(events::Connection(i, true)),
+            ConnectionStatusVar(i, Offline) => {
+                self.emit(events::Connection(i, false));
+                self.friend_offline(i);
+            },
+            TypingChange(i, typing) => self.emit(events::Typing(i, typing)),
+            ReadReceipt(i, receipt) => self.emit(events::Delivered(i, receipt)),
+            FileSendRequest(i, file, size, name) => {
+                self.tox_file_request(i, file, size, name);
+            },
+            FileControl(i, ty, file, control, _) => {
+                self.tox_file_control(i, direction(ty), file, control);
+            },
+            FileData(i, file, data) => self.tox_file_data(i, file, data),
+            _ => { }
+        }
+    }
+
+    fn av_event(&mut self, av: &Av, e: AvEvent) {
+        match e {
+            AvInvite(index) => self.av_invite(av, index),
+            AvStart(index) => self.av_start(av, index),
+            AvCancel(index) => self.end_call(index, "missed call"),
+            AvReject(index) => self.end_call(index, "call declined"),
+            AvEnd(index) => self.end_call(index, "call ended"),
+            AvRequestTimeout(index) => self.end_call(index, "no answer"),
+            AvPeerTimeout(index) => self.end_call(index, "call lost"),
+            AvAudio(index, frame) => self.calls.play(index, frame.as_slice()),
+            _ => { },
+        }
+    }
+
+    /// Returns toxav or, if calls are turned off, tells the user so.
+    fn av(&mut self) -> Option<Rc<Av>> {
+        match self.av {
+            Some(ref av) => return Some(av.clone()),
+            None => { },
+        }
+        self.error("calls are turned off".to_string());
+        None
+    }
+
+    /// A friend is calling us. The call is only answered when the user says so.
+    fn av_invite(&mut self, av: &Av, index: i32) {
+        let friend = match av.peer(index) {
+            Ok(f) => f,
+            Err(_) => return,
+        };
+        self.calls.ringing(index, friend);
+        self.emit(events::CallChanged(friend, Some(Ringing)));
+        let name = self.friend_name(friend);
+        self.info(format!("{} is calling, :answer or :hangup", name));
+    }
+
+    fn answer_call(&mut self) {
+        match self.calls.incoming().map(|c| c.friend) {
+            Some(friend) => self.answer(friend, true),
+            None => self.error("nobody is calling".to_string()),
+        }
+    }
+
+    /// Answers or declines the call of `friend`, who's calling us.
+    pub fn answer(&mut self, friend: i32, accept: bool) {
+        let call = self.calls.with_friend(friend).map(|c| (c.index, c.state));
+        let index = match call {
+            Some((index, Ringing)) => index,
+            _ => {
+                let name = self.friend_name(friend);
+                self.error(format!("{} isn't calling", name));
+                return;
+            },
+        };
+        let av = match self.av() {
+            Some(av) => av,
+            None => return,
+        };
+        if !accept {
+            av.reject(index).ok();
+            self.end_call(index, "declined call");
+        } else if av.answer(index).is_err() {
+            self.error("could not answer the call".to_string());
+            self.end_call(index, "missed call");
+        }
+    }
+
+    /// Both sides have agreed on the call `index`.
+    fn av_start(&mut self, av: &Av, index: i32) {
+        let friend = match self.calls.get(index) {
+            Some(c) => c.friend,
+            None => return,
+        };
+        match self.calls.start(index) {
+            Ok(_) => {
+                self.clock.send(true);
+                self.emit(events::CallChanged(friend, Some(InCall)));
+                let name = self.friend_name(friend);
+                self.info(format!("in a call with {}", name));
+            },
+            Err(e) => {
+                av.hangup(index).ok();
+                self.end_call(index, "call ended");
+                self.error(format!("could not start the call: {}", e));
+            },
+        }
+    }
+
+    /// Forgets the call `index` and tells the user why it ended.
+    fn end_call(&mut self, index: i32, why: &str) {
+        let call = match self.calls.end(index) {
+            Some(c) => c,
+            None => return,
+        };
+        if call.state == InCall {
+            self.clock.send(false);
+        }
+        self.emit(events::CallChanged(call.friend, None));
+        let name = self.friend_name(call.friend);
+        self.info(format!("{} with {}", why, name));
+    }
+
+    fn send_audio(&mut self) {
+        let index = match self.calls.active() {
+            Some(c) => c.index,
+            None => return,
+        };
+        let frame = match self.calls.capture() {
+            Some(frame) => frame,
+            None => return,
+        };
+        match self.av {
+            Some(ref av) => { av.send_audio(index, frame.as_slice()).ok(); },
+            None => { },
+        }
+    }
+
+    fn call(&mut self, friend: Option<i32>) {
+        let friend = match friend {
+            Some(f) => f,
+            None => {
+                self.error("no friend selected".to_string());
+                return;
+            },
+        };
+        let name = self.friend_name(friend);
+        if self.calls.with_friend(friend).is_some() {
+            self.error(format!("already calling {}", name));
+            return;
+        }
+        let av = match self.av() {
+            Some(av) => av,
+            None => return,
+        };
+        match av.call(friend, RING_TIMEOUT) {
+            Ok(index) => {
+                self.calls.calling(index, friend);
+                self.emit(events::CallChanged(friend, Some(Calling)));
+                self.info(format!("calling {}", name));
+            },
+            Err(_) => self.error(format!("could not call {}", name)),
+        }
+    }
+
+    fn hang_up(&mut self) {
+        let call = self.calls.current().or(self.calls.incoming()).map(|c| {
+            (c.index, c.friend, c.state)
+        });
+        let (index, friend, state) = match call {
+            Some(c) => c,
+            None => {
+                self.error("not in a call".to_string());
+                return;
+            },
+        };
+        let av = match self.av() {
+            Some(av) => av,
+            None => return,
+        };
+        match state {
+            Calling => { av.cancel(index, friend).ok(); },
+            Ringing => {
+                av.reject(index).ok();
+                self.end_call(index, "declined call");
+                return;
+            },
+            InCall => { av.hangup(index).ok(); },
+        }
+        self.end_call(index, "call ended");
+    }
+
+    /// Returns the friend, file number, direction and state of the transfer `id`.
+    fn transfer(&mut self, id: uint) -> Option<(i32, u8, Direction, State)> {
+        self.transfers.get(id).map(|t| (t.friend, t.file, t.direction, t.state))
+    }
+
+    fn set_state(&mut self, id: uint, state: State) {
+        match self.transfers.get(id) {
+            Some(t) => t.state = state,
+            None => return,
+        }
+        self.emit(events::TransferChanged(id, state));
+    }
+
+    fn transfer_name(&mut self, id: uint) -> String {
+        match self.transfers.get(id) {
+            Some(t) => t.name.clone(),
+            None => format!("transfer {}", id),
+        }
+    }
+
+    /// Offers the file at `path` to `friend`.
+    fn send_file(&mut self, path: Path, friend: Option<i32>) {
+        let friend = match friend {
+            Some(f) => f,
+            None => {
+                self.error("no friend selected".to_string());
+                return;
+            },
+        };
+        let size = match stat(&path) {
+            Ok(s) => s.size,
+            Err(e) => {
+                self.error(format!("{}: {}", path.display(), e));
+                return;
+            },
+        };
+        let name = match path.filename_str() {
+            Some(n) => n.to_string(),
+            None => {
+                self.error(format!("{} is not a file", path.display()));
+                return;
+            },
+        };
+        let peer = self.friend_name(friend);
+        match self.tox.new_file_sender(friend, size, Path::new(name.as_slice())) {
+            Ok(file) => {
+                let id = self.transfers.add(friend, file as u8, Upload, peer.clone(),
+                                            name.clone(), path.clone(), size);
+                self.io.send(transfers::OpenRead(id, path));
+                self.emit(events::TransferChanged(id, Offered));
+                self.info(format!("offered {} to {}", name, peer));
+            },
+            Err(_) => self.error(format!("could not send {} to {}", name, peer)),
+        }
+    }
+
+    fn tox_file_request(&mut self, friend: i32, file: u8, size: u64, name: Vec<u8>) {
+        let name = String::from_utf8_lossy(name.as_slice()).into_string();
+        // Don't let the sender choose where the file goes.
+        let name = match Path::new(name.as_slice()).filename_str() {
+            Some(n) => n.to_string(),
+            None => "unnamed".to_string(),
+        };
+        let peer = self.friend_name(friend);
+        let path = self.download_dir.join(name.as_slice());
+        let id = self.transfers.add(friend, file, Download, peer.clone(), name.clone(),
+                                    path, size);
+        self.emit(events::FileOffer(id, friend, name.clone(), size));
+        self.info(format!("{} offers {}, :file accept {} to save it", peer, name, id));
+    }
+
+    fn tox_file_control(&mut self, friend: i32, direction: Direction, file: u8,
+                        control: tox::core::ControlType) {
+        let id = match self.transfers.find(friend, file, direction) {
+            Some(id) => id,
+            None => return,
+        };
+        match control {
+            ControlAccept => {
+                self.set_state(id, Active);
+                if direction == Upload {
+                    self.send_next(id);
+                }
+            },
+            ControlPause => self.set_state(id, Paused),
+            ControlKill => {
+                self.set_state(id, Cancelled);
+                self.io.send(transfers::Close(id));
+                let name = self.transfer_name(id);
+                let peer = self.friend_name(friend);
+                self.warning(format!("{} cancelled {}", peer, name));
+            },
+            ControlFinished if direction == Download => {
+                self.set_state(id, Done);
+                self.io.send(transfers::Close(id));
+                // The sender waits for us to confirm that we've got everything.
+                self.tox.file_send_control(friend, Receiving, file, ControlFinished,
+                                           Vec::new()).ok();
+                let path = match self.transfers.get(id) {
+                    Some(t) => t.path.display().to_string(),
+                    None => return,
+                };
+                self.info(format!("saved {}", path));
+            },
+            _ => { },
+        }
+    }
+
+    fn tox_file_data(&mut self, friend: i32, file: u8, data: Vec<u8>) {
+        let id = match self.transfers.find(friend, file, Download) {
+            Some(id) => id,
+            None => return,
+        };
+        match self.transfers.get(id) {
+            Some(t) => t.done += data.len() as u64,
+            None => { },
+        }
+        self.io.send(transfers::WriteData(id, data));
+    }
+
+    fn io_event(&mut self, e: IoEvent) {
+        match e {
+            transfers::Data(id, data) => self.send_data(id, data),
+            transfers::Eof(id) => self.upload_done(id),
+            transfers::Ready(id) => self.send_next(id),
+            transfers::IoError(id, msg) => {
+                let name = self.transfer_name(id);
+                self.error(format!("{}: {}", name, msg));
+                self.stop_transfer(id, Failed);
+            },
+        }
+    }
+
+    /// Sends the next piece of an upload.
+    fn send_next(&mut self, id: uint) {
+        let pending = match self.transfers.get(id) {
+            Some(t) if t.state == Active => t.pending.take(),
+            _ => return,
+        };
+        match pending {
+            Some(data) => self.send_data(id, data),
+            None => {
+                let friend = match self.transfer(id) {
+                    Some((friend, _, _, _)) => friend,
+                    None => return,
+                };
+                let size = self.tox.file_data_size(friend).unwrap_or(1024);
+                self.io.send(transfers::Read(id, size as uint));
+            },
+        }
+    }
+
+    fn send_data(&mut self, id: uint, data: Vec<u8>) {
+        let (friend, file) = match self.transfer(id) {
+            Some((friend, file, _, Active)) => (friend, file),
+            Some(_) => {
+                // Paused, keep the data for later.
+                self.transfers.get(id).unwrap().pending = Some(data);
+                return;
+            },
+            None => return,
+        };
+        let len = data.len();
+        if self.tox.file_send_data(friend, file, data.clone()).is_ok() {
+            self.transfers.get(id).unwrap().done += len as u64;
+            self.send_next(id);
+        } else {
+            // Tox's buffer is full. Try again in a moment.
+            self.transfers.get(id).unwrap().pending = Some(data);
+            self.io.send(transfers::Delay(id));
+        }
+    }
+
+    fn upload_done(&mut self, id: uint) {
+        let (friend, file) = match self.transfer(id) {
+            Some((friend, file, _, _)) => (friend, file),
+            None => return,
+        };
+        self.io.send(transfers::Close(id));
+        if self.tox.file_send_control(friend, Sending, file, ControlFinished,
+                                      Vec::new()).is_err() {
+            let name = self.transfer_name(id);
+            self.error(format!("could not finish sending {}", name));
+            self.set_state(id, Failed);
+            return;
+        }
+        self.set_state(id, Done);
+        let name = self.transfer_name(id);
+        let peer = self.friend_name(friend);
+        self.info(format!("sent {} to {}", name, peer));
+    }
+
+    fn file_control(&mut self, action: FileAction, id: uint) {
+        match action {
+            FileAccept => self.file_accept(id),
+            FilePause => self.file_pause(id),
+            FileResume => self.file_resume(id),
+            FileCancel => self.file_cancel(id),
+        }
+    }
+
+    fn file_accept(&mut self, id: uint) {
+        let (friend, file) = match self.transfer(id) {
+            Some((friend, file, Download, Offered)) => (friend, file),
+            Some(_) => {
+                self.error(format!("transfer {} can't be accepted", id));
+                return;
+            },
+            None => {
+                self.error(format!("unknown transfer {}", id));
+                return;
+            },
+        };
+        match mkdir_recursive(&self.download_dir, UserDir) {
+            Err(e) => {
+                let dir = self.download_dir.display().to_string();
+                self.error(format!("could not create {}: {}", dir, e));
+                return;
+            },
+            _ => { },
+        }
+        // Only create the file once the sender knows we want it. The data can't
+        // arrive before the file is open because the io task handles the requests in
+        // order.
+        if self.tox.file_send_control(friend, Receiving, file, ControlAccept,
+                                      Vec::new()).is_err() {
+            let name = self.transfer_name(id);
+            self.error(format!("could not accept {}", name));
+            return;
+        }
+        let path = unique_path(self.transfers.get(id).unwrap().path.clone());
+        self.io.send(transfers::OpenWrite(id, path.clone()));
+        self.transfers.get(id).unwrap().path = path;
+        self.set_state(id, Active);
+    }
+
+    fn file_pause(&mut self, id: uint) {
+        match self.transfer(id) {
+            Some((friend, file, direction, Active)) => {
+                let ty = transfer_type(direction);
+                let res = self.tox.file_send_control(friend, ty, file, ControlPause,
+                                                     Vec::new());
+                match res {
+                    Ok(_) => self.set_state(id, Paused),
+                    Err(_) => self.error(format!("could not pause transfer {}", id)),
+                }
+            },
+            Some(_) => self.error(format!("transfer {} isn't active", id)),
+            None => self.error(format!("unknown transfer {}", id)),
+        }
+    }
+
+    fn file_resume(&mut self, id: uint) {
+        match self.transfer(id) {
+            Some((friend, file, direction, Paused)) => {
+                let ty = transfer_type(direction);
+                let res = self.tox.file_send_control(friend, ty, file, ControlAccept,
+                                                     Vec::new());
+                match res {
+                    Ok(_) => {
+                        self.set_state(id, Active);
+                        if direction == Upload {
+                            self.send_next(id);
+                        }
+                    },
+                    Err(_) => self.error(format!("could not resume transfer {}", id)),
+                }
+            },
+            Some(_) => self.error(format!("transfer {} isn't paused", id)),
+            None => self.error(format!("unknown transfer {}", id)),
+        }
+    }
+
+    fn file_cancel(&mut self, id: uint) {
+        match self.transfer(id) {
+            Some((_, _, _, state)) if !state.finished() => {
+                self.stop_transfer(id, Cancelled);
+            },
+            Some(_) => self.error(format!("transfer {} is finished", id)),
+            None => self.error(format!("unknown transfer {}", id)),
+        }
+    }
+
+    /// Fails the transfers with `friend`. Tox forgets about them when a friend goes
+    /// offline.
+    fn friend_offline(&mut self, friend: i32) {
+        for id in self.transfers.with_friend(friend).move_iter() {
+            self.io.send(transfers::Close(id));
+            self.set_state(id, Failed);
+            let name = self.transfer_name(id);
+            let peer = self.friend_name(friend);
+            self.warning(format!("{} failed, {} went offline", name, peer));
+        }
+    }
+
+    /// Tells the friend that we've given up on the transfer `id`.
+    fn stop_transfer(&mut self, id: uint, state: State) {
+        match self.transfer(id) {
+            Some((friend, file, direction, _)) => {
+                self.tox.file_send_control(friend, transfer_type(direction), file,
+                                           ControlKill, Vec::new()).ok();
+            },
+            None => return,
+        }
+        self.io.send(transfers::Close(id));
+        self.set_state(id, state);
+    }
+}
@@ -7,6 +7,9 @@ extern crate signals;
 extern crate fdpoll;
 extern crate debug;
 extern crate libc;
+extern crate getopts;
+extern crate serialize;
+extern crate time;
 
 mod colors;
 mod ui;
@@ -15,7 +18,27 @@ mod contacts;
 mod utfbuf;
 mod prompt;
 mod commands;
+mod chat;
+mod profile;
+mod config;
+mod args;
+mod keys;
+mod history;
+mod text;
+mod pager;
+mod addressbook;
+mod transfers;
+mod calls;
+mod events;
+mod headless;
+mod session;
+mod control;
+mod notify;
 
 fn main() {
-    ui::run();
+    let args = std::os::args();
+    match args::parse(args.as_slice()) {
+        Some(opts) => session::run(opts),
+        None => { },
+    }
 }
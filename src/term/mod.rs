@@ -1,9 +1,38 @@
 extern crate libc;
 
+use serialize::base64::{ToBase64, STANDARD};
+use std::io::{stdout, IoResult};
+
 pub use self::term::{dimensions};
 
 pub mod cwidth;
 
+/// Asks the terminal to put `s` into the clipboard.
+///
+/// This uses the OSC 52 escape sequence which isn't supported by all terminals.
+pub fn set_clipboard(s: &str) -> IoResult<()> {
+    let mut out = stdout();
+    try!(write!(out, "\x1b]52;c;{}\x07", s.as_bytes().to_base64(STANDARD)));
+    out.flush()
+}
+
+/// Asks the terminal to show a notification, which usually marks the window as
+/// urgent as well.
+///
+/// This uses the OSC 777 escape sequence which isn't supported by all terminals.
+pub fn notify(title: &str, body: &str) -> IoResult<()> {
+    let mut out = stdout();
+    try!(write!(out, "\x1b]777;notify;{};{}\x07", osc_field(title), osc_field(body)));
+    out.flush()
+}
+
+/// Replaces the characters that can't be in a field of an OSC 777 sequence with
+/// spaces. Control characters would end the sequence early and `;` separates the
+/// fields.
+fn osc_field(s: &str) -> String {
+    s.chars().map(|c| if c.is_control() || c == ';' { ' ' } else { c }).collect()
+}
+
 #[cfg(windows)]
 mod term {
     use libc::{HANDLE, DWORD, BOOL, SHORT, WORD};
@@ -86,3 +115,10 @@ mod term {
         (w.ws_col as uint, w.ws_row as uint)
     }
 }
+
+#[test]
+fn test_osc_field() {
+    assert_eq!(osc_field("a;b").as_slice(), "a b");
+    assert_eq!(osc_field("x\x07y\x1b]z\nw\u009c").as_slice(), "x y ]z w ");
+    assert_eq!(osc_field("hëllo").as_slice(), "hëllo");
+}
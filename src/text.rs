@@ -0,0 +1,66 @@
+use std::cmp::{min};
+
+/// Converts `s` to lower case.
+///
+/// Unlike the ascii methods this works for all of unicode.
+pub fn lowercase(s: &str) -> String {
+    s.chars().map(|c| c.to_lowercase()).collect()
+}
+
+/// Returns whether `s` contains `pattern` ignoring case.
+///
+/// `pattern` has to be lower case already.
+pub fn contains_lowercase(s: &str, pattern: &str) -> bool {
+    lowercase(s).as_slice().contains(pattern)
+}
+
+/// Returns whether `text` contains `name` as a word of its own, ignoring case.
+pub fn mentions(text: &str, name: &str) -> bool {
+    let name = lowercase(name.trim());
+    if name.len() == 0 {
+        return false;
+    }
+    let text = lowercase(text);
+    let text = text.as_slice();
+    let boundary = |c: Option<char>| c.map_or(true, |c| !c.is_alphanumeric());
+    let mut start = 0;
+    loop {
+        let begin = match text.slice_from(start).find_str(name.as_slice()) {
+            Some(pos) => start + pos,
+            None => return false,
+        };
+        let end = begin + name.len();
+        if boundary(text.slice_to(begin).chars().next_back())
+                && boundary(text.slice_from(end).chars().next()) {
+            return true;
+        }
+        start = text.char_range_at(begin).next;
+    }
+}
+
+/// Returns the number of characters that have to be inserted, deleted or replaced to
+/// turn `a` into `b`.
+pub fn edit_distance(a: &str, b: &str) -> uint {
+    let b: Vec<char> = b.chars().collect();
+    let mut prev: Vec<uint> = range(0, b.len() + 1).collect();
+    for (i, ca) in a.chars().enumerate() {
+        let mut cur = vec!(i + 1);
+        for (j, &cb) in b.iter().enumerate() {
+            let replace = *prev.get(j) + if ca == cb { 0 } else { 1 };
+            let insert = *cur.get(j) + 1;
+            let delete = *prev.get(j + 1) + 1;
+            cur.push(min(replace, min(insert, delete)));
+        }
+        prev = cur;
+    }
+    *prev.get(b.len())
+}
+
+#[test]
+fn test_mentions() {
+    assert!(mentions("hey Stannis, you there?", "stannis"));
+    assert!(mentions("STANNIS", "Stannis "));
+    assert!(!mentions("stannisbaratheon", "stannis"));
+    assert!(mentions("stannisbaratheon and stannis", "stannis"));
+    assert!(!mentions("anything", ""));
+}
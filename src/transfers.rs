@@ -0,0 +1,281 @@
+use nc = ncurses;
+use colors::*;
+use std::collections::{HashMap};
+use profile::{open_private};
+use std::io::{File, EndOfFile, Truncate};
+use std::io::timer::{sleep};
+
+/// Requests to the task that reads and writes the files of the transfers.
+pub enum IoRequest {
+    OpenRead(uint, Path),
+    OpenWrite(uint, Path),
+    /// Read at most this many bytes.
+    Read(uint, uint),
+    WriteData(uint, Vec<u8>),
+    Close(uint),
+    /// Answer with `Ready` after a short while.
+    Delay(uint),
+}
+
+pub enum IoEvent {
+    Data(uint, Vec<u8>),
+    Eof(uint),
+    Ready(uint),
+    IoError(uint, String),
+}
+
+/// How long to wait before we try to send data that tox didn't accept again.
+static RETRY_MS: u64 = 20;
+
+/// Starts the task that does the file I/O so that slow disks don't block the ui.
+pub fn spawn_io() -> (Sender<IoRequest>, Receiver<IoEvent>) {
+    let (req_tx, req_rx) = channel();
+    let (ev_tx, ev_rx) = channel();
+    spawn(proc() {
+        let mut files: HashMap<uint, File> = HashMap::new();
+        for req in req_rx.iter() {
+            let ev = match req {
+                OpenRead(id, path) => match File::open(&path) {
+                    Ok(f) => { files.insert(id, f); None },
+                    Err(e) => Some(IoError(id, e.to_string())),
+                },
+                // Downloads may be private, so only the user can read them.
+                OpenWrite(id, path) => match open_private(&path, Truncate) {
+                    Ok(f) => { files.insert(id, f); None },
+                    Err(e) => Some(IoError(id, e.to_string())),
+                },
+                Read(id, n) => match files.find_mut(&id) {
+                    Some(f) => {
+                        let mut buf = Vec::from_elem(n, 0u8);
+                        match f.read(buf.as_mut_slice()) {
+                            Ok(0) => Some(Eof(id)),
+                            Ok(len) => {
+                                buf.truncate(len);
+                                Some(Data(id, buf))
+                            },
+                            Err(ref e) if e.kind == EndOfFile => Some(Eof(id)),
+                            Err(e) => Some(IoError(id, e.to_string())),
+                        }
+                    },
+                    None => None,
+                },
+                WriteData(id, data) => match files.find_mut(&id) {
+                    Some(f) => match f.write(data.as_slice()) {
+                        Ok(_) => None,
+                        Err(e) => Some(IoError(id, e.to_string())),
+                    },
+                    None => None,
+                },
+                Close(id) => {
+                    files.remove(&id);
+                    None
+                },
+                Delay(id) => {
+                    sleep(RETRY_MS);
+                    Some(Ready(id))
+                },
+            };
+            match ev {
+                Some(ev) => if ev_tx.send_opt(ev).is_err() { break },
+                None => { },
+            }
+        }
+    });
+    (req_tx, ev_rx)
+}
+
+#[deriving(PartialEq)]
+pub enum Direction {
+    Upload,
+    Download,
+}
+
+#[deriving(PartialEq)]
+pub enum State {
+    /// Waiting for the receiver to accept the file.
+    Offered,
+    Active,
+    Paused,
+    Done,
+    Cancelled,
+    Failed,
+}
+
+impl State {
+    pub fn fmt(self) -> &'static str {
+        match self {
+            Offered => "offered",
+            Active => "active",
+            Paused => "paused",
+            Done => "done",
+            Cancelled => "cancelled",
+            Failed => "failed",
+        }
+    }
+
+    /// Whether the transfer can't change anymore.
+    pub fn finished(self) -> bool {
+        self == Done || self == Cancelled || self == Failed
+    }
+}
+
+pub struct Transfer {
+    pub id: uint,
+    pub friend: i32,
+    /// The file number tox uses.
+    pub file: u8,
+    pub direction: Direction,
+    /// The name of the friend.
+    pub peer: String,
+    pub name: String,
+    pub path: Path,
+    pub size: u64,
+    pub done: u64,
+    /// Bytes per second.
+    rate: u64,
+    last_done: u64,
+    pub state: State,
+    /// Data that tox didn't accept yet.
+    pub pending: Option<Vec<u8>>,
+}
+
+/// Formats a number of bytes.
+pub fn fmt_size(n: u64) -> String {
+    if n < 1024 {
+        format!("{} B", n)
+    } else if n < 1024 * 1024 {
+        format!("{:.1f} KiB", n as f64 / 1024.0)
+    } else if n < 1024 * 1024 * 1024 {
+        format!("{:.1f} MiB", n as f64 / (1024.0 * 1024.0))
+    } else {
+        format!("{:.1f} GiB", n as f64 / (1024.0 * 1024.0 * 1024.0))
+    }
+}
+
+impl Transfer {
+    fn fmt(&self) -> String {
+        let arrow = match self.direction {
+            Upload => "to",
+            Download => "from",
+        };
+        let percent = if self.size > 0 { self.done * 100 / self.size } else { 100 };
+        let mut s = format!("{:>3}  {} {} {}   {} / {} ({}%)   {}", self.id, self.name,
+                            arrow, self.peer, fmt_size(self.done), fmt_size(self.size),
+                            percent, self.state.fmt());
+        if self.state == Active {
+            s.push_str(format!("   {}/s", fmt_size(self.rate)).as_slice());
+        }
+        s
+    }
+}
+
+/// All file transfers of this session.
+pub struct Transfers {
+    list: Vec<Transfer>,
+    next_id: uint,
+}
+
+impl Transfers {
+    pub fn new() -> Transfers {
+        Transfers {
+            list: Vec::new(),
+            next_id: 1,
+        }
+    }
+
+    /// Adds a transfer that has just been offered and returns its id.
+    pub fn add(&mut self, friend: i32, file: u8, direction: Direction, peer: String,
+               name: String, path: Path, size: u64) -> uint {
+        let id = self.next_id;
+        self.next_id += 1;
+        self.list.push(Transfer {
+            id: id,
+            friend: friend,
+            file: file,
+            direction: direction,
+            peer: peer,
+            name: name,
+            path: path,
+            size: size,
+            done: 0,
+            rate: 0,
+            last_done: 0,
+            state: Offered,
+            pending: None,
+        });
+        id
+    }
+
+    pub fn get<'a>(&'a mut self, id: uint) -> Option<&'a mut Transfer> {
+        self.list.mut_iter().find(|t| t.id == id)
+    }
+
+    /// Returns the id of the unfinished transfer that tox knows as `file`.
+    pub fn find(&self, friend: i32, file: u8, direction: Direction) -> Option<uint> {
+        self.list.iter().find(|t| {
+            t.friend == friend && t.file == file && t.direction == direction
+                && !t.state.finished()
+        }).map(|t| t.id)
+    }
+
+    /// Returns the ids of the unfinished transfers with `friend`.
+    pub fn with_friend(&self, friend: i32) -> Vec<uint> {
+        self.list.iter().filter(|t| t.friend == friend && !t.state.finished())
+                 .map(|t| t.id).collect()
+    }
+
+    /// Updates the rates. Called every second.
+    pub fn tick(&mut self) {
+        for t in self.list.mut_iter() {
+            t.rate = t.done - t.last_done;
+            t.last_done = t.done;
+        }
+    }
+
+    /// Whether any transfer is active.
+    pub fn active(&self) -> bool {
+        self.list.iter().any(|t| t.state == Active)
+    }
+
+    pub fn len(&self) -> uint {
+        self.list.len()
+    }
+
+    /// Returns the transfer in row `i` of the list.
+    pub fn nth<'a>(&'a self, i: uint) -> Option<&'a Transfer> {
+        if i < self.list.len() {
+            Some(self.list.get(i))
+        } else {
+            None
+        }
+    }
+
+    /// Draws the list into the `height` lines starting at `top` with row `selected`
+    /// highlighted.
+    pub fn draw(&self, top: i32, height: uint, selected: uint) {
+        let first = if selected >= height { selected + 1 - height } else { 0 };
+        let mut y = top;
+        if self.list.len() == 0 {
+            normal!(COLOR_PAIR_DEFAULT);
+            nc::mvaddstr(y, 0, " no transfers");
+            nc::clrtoeol();
+            y += 1;
+        }
+        for (i, t) in self.list.iter().enumerate().skip(first).take(height) {
+            if i == selected {
+                bold!(COLOR_PAIR_SELECTED);
+            } else {
+                normal!(COLOR_PAIR_DEFAULT);
+            }
+            nc::mvaddstr(y, 0, t.fmt().as_slice());
+            nc::clrtoeol();
+            y += 1;
+        }
+        normal!(COLOR_PAIR_DEFAULT);
+        while y < top + height as i32 {
+            nc::move(y, 0);
+            nc::clrtoeol();
+            y += 1;
+        }
+    }
+}
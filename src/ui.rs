@@ -1,66 +1,105 @@
 use nc = ncurses;
-use signals::{Signals, Pipe, Hangup, Terminate, WinSize};
-use tox::core::{Tox, Address, FaerrToolong, FaerrOwnkey, FaerrAlreadysent,
-                FaerrBadchecksum, Event, NameChange};
-use fdpoll::{FDPoll, Read};
+use tox::core::{UserStatusBusy};
 use term;
 use colors::*;
+use colors;
 use contacts;
-use commands::{Quit, Add, Del};
+use commands::{Command, Del, MyId, ToClipboard, Source, Help, Messages, ShowTransfers};
 use commands;
+use config;
+use config::{Config};
+use transfers::{fmt_size};
+use calls::{Ringing};
+use events;
+use events::{Event};
+use notify::{Notifier};
+use session::{Session, Frontend};
+use time;
+
+use std::mem;
+
+/// How long messages stay in the status line in seconds.
+static INFO_TIMEOUT: uint = 5;
+static ERROR_TIMEOUT: uint = 15;
+
+/// Number of messages we keep in the log.
+static MAX_LOG: uint = 500;
+
+/// Something the user answers with y or n.
+enum Question {
+    DeleteFriend(i32),
+    /// Whether to save the file of the transfer.
+    AcceptFile(uint),
+    /// Whether to answer the call of the friend.
+    TakeCall(i32),
+}
 
-use std::rc::{Rc};
-use std::comm::{Select};
-
-pub fn bootstrap() -> Tox {
-    let tox = Tox::new(true).unwrap();
-
-    let addresses = [
-        ("192.254.75.98",   33445, "951C88B7E75C867418ACDB5D273821372BB5BD652740BCDF623A4FA293E75D2F"),
-        ("144.76.60.215",   33445, "04119E835DF3E78BACF0F84235B300546AF8B936F035185E2A8E9E0A67C8924F"),
-        ("23.226.230.47",   33445, "A09162D68618E742FFBCA1C2C70385E6679604B2D80EA6E84AD0996A1AC8A074"),
-        ("37.187.20.216",   33445, "4FD54CFD426A338399767E56FD0F44F5E35FA8C38C8E87C8DC3FEAC0160F8E17"),
-        ("54.199.139.199",  33445, "7F9C31FE850E97CEFD4C4591DF93FC757C7C12549DDD55F8EEAECC34FE76C029"),
-        ("109.169.46.133",  33445, "7F31BFC93B8E4016A902144D0B110C3EA97CB7D43F1C4D21BCAE998A7C838821"),
-        ("192.210.149.121", 33445, "F404ABAA1C99A9D37D61AB54898F56793E1DEF8BD46B1038B9D822E8460FAB67"),
-    ];
-
-    for &(ip, port, key) in addresses.iter() {
-        let ip = ip.to_string();
-        let key = box from_str(key).unwrap();
-        if tox.bootstrap_from_address(ip, true, port, key).is_ok() {
-            break;
+impl Question {
+    /// Whether the question is about what was selected when it was asked. These are
+    /// dropped when the user moves on.
+    fn about_focus(&self) -> bool {
+        match *self {
+            DeleteFriend(_) => true,
+            AcceptFile(_) | TakeCall(_) => false,
         }
     }
-
-    tox
-}
-
-pub fn run() {
-    let tox = Rc::new(bootstrap());
-    let mut ui = Ui::new(tox);
-    ui.run();
 }
 
 enum StatusMessage {
     NoMsg,
-    Error(&'static str),
+    Info(String),
+    Warning(String),
+    Error(String),
+}
+
+impl StatusMessage {
+    fn level(&self) -> &'static str {
+        match *self {
+            NoMsg => "",
+            Info(_) => "info",
+            Warning(_) => "warning",
+            Error(_) => "error",
+        }
+    }
+
+    fn color(&self) -> i16 {
+        match *self {
+            Warning(_) => COLOR_PAIR_WARNING,
+            Error(_) => COLOR_PAIR_STATUS,
+            _ => COLOR_PAIR_INFO,
+        }
+    }
+
+    fn timeout(&self) -> uint {
+        match *self {
+            Info(_) => INFO_TIMEOUT,
+            _ => ERROR_TIMEOUT,
+        }
+    }
 }
 
-struct Ui<'a> {
-    tox: Rc<Tox>,
+/// The curses front end.
+pub struct Ui<'a> {
+    /// Path of the configuration file.
+    config: Path,
     contacts: contacts::View<'a>,
-    shutdown: bool,
     status: StatusMessage,
+    /// Seconds until the status message is cleared.
+    status_timeout: uint,
+    /// The messages we've shown in the status line.
+    log: Vec<String>,
+    /// The questions we're waiting for answers to and their text. The first one is
+    /// shown in the status line.
+    questions: Vec<(Question, String)>,
+    notifier: Notifier,
     needs_update: bool,
 }
 
 impl<'a> Ui<'a> {
-    pub fn new(tox: Rc<Tox>) -> Ui {
+    pub fn new(session: &mut Session, config_path: Path, config: Config) -> Ui {
         use libc::{c_int, c_char};
         extern {
             fn use_default_colors() -> c_int;
-            fn set_escdelay(size: c_int) -> c_int;
             fn setlocale(category: c_int, locale: *const c_char) -> *const c_char;
         }
         unsafe { setlocale(0, [0i8].as_ptr()); }
@@ -68,176 +107,419 @@ impl<'a> Ui<'a> {
         nc::start_color();
         unsafe { use_default_colors(); }
         nc::cbreak();
-        unsafe { set_escdelay(25); }
         nc::keypad(nc::constants::stdscr, true);
         nc::noecho();
         nc::nonl();
 
-        nc::init_color(COLOR_BACKGROUND,  0x1C * 4, 0x1C * 4, 0x1C * 4);
-        nc::init_color(COLOR_HEADERS,     0xA3 * 4, 0x81 * 4, 0xA6 * 4);
-        nc::init_color(COLOR_ENTRY,       0xDD * 4, 0xDD * 4, 0xDD * 4);
-        nc::init_color(COLOR_SEPARATOR,   0x2B * 4, 0x33 * 4, 0x36 * 4);
-        nc::init_color(COLOR_SELECTED_BG, 0x40 * 4, 0x40 * 4, 0x40 * 4);
-        nc::init_color(COLOR_STATUS_BG,   0x61 * 4, 0x20 * 4, 0x20 * 4);
-        nc::init_color(COLOR_PROMPT_BG,   0x36 * 4, 0x20 * 4, 0x65 * 4);
-
-        nc::init_pair(COLOR_PAIR_DEFAULT,   COLOR_ENTRY,     COLOR_BACKGROUND);
-        nc::init_pair(COLOR_PAIR_HEADER,    COLOR_HEADERS,   COLOR_BACKGROUND);
-        nc::init_pair(COLOR_PAIR_SEPARATOR, COLOR_SEPARATOR, COLOR_BACKGROUND);
-        nc::init_pair(COLOR_PAIR_SELECTED,  COLOR_ENTRY,     COLOR_SELECTED_BG);
-        nc::init_pair(COLOR_PAIR_STATUS,    COLOR_ENTRY,     COLOR_STATUS_BG);
-        nc::init_pair(COLOR_PAIR_PROMPT,    COLOR_ENTRY,     COLOR_PROMPT_BG);
-
-        nc::bkgd(' ' as u32 | nc::COLOR_PAIR(COLOR_PAIR_DEFAULT) as u32);
+        let mut contacts = contacts::View::new();
+        contacts.load_history(&session.profile.dir_path());
 
-        Ui {
-            tox: tox,
-            contacts: contacts::View::new(),
-            shutdown: false,
+        let mut ui = Ui {
+            config: config_path,
+            contacts: contacts,
             status: NoMsg,
+            status_timeout: 0,
+            log: Vec::new(),
+            questions: Vec::new(),
+            notifier: Notifier::new(),
             needs_update: true,
+        };
+        for &id in session.tox.get_friendlist().iter() {
+            ui.add_to_view(session, id);
+        }
+        for addr in session.addressbook.addresses().move_iter() {
+            ui.contacts.remember_address(addr);
         }
+        ui.apply_config(session, config);
+        let name = session.tox.get_self_name().unwrap_or(String::new());
+        let status = session.tox.get_self_status_message().unwrap_or(String::new());
+        ui.contacts.set_self(name, status, session.tox.get_self_user_status());
+        ui
     }
 
-    pub fn run(&mut self) {
-        let sigs = Signals::new().unwrap();
-        // sigs.subscribe(Interrupt);
-        sigs.subscribe(Hangup);
-        sigs.subscribe(Terminate);
-        sigs.subscribe(Pipe);
-        sigs.subscribe(WinSize);
-
-        let fdpoll = FDPoll::new(10).unwrap();
-        fdpoll.add(0, Read).unwrap();
-        fdpoll.wait().unwrap();
-
-        let select = Select::new();
-        let mut fdpoll_hdl = select.handle(&fdpoll.rcv);
-        unsafe { fdpoll_hdl.add(); }
-        let mut sig_hdl = select.handle(sigs.receiver());
-        unsafe { sig_hdl.add(); }
-        let tox = self.tox.clone();
-        let mut tox_hdl = select.handle(&tox.events);
-        unsafe { tox_hdl.add(); }
-
-        loop {
-            self.update();
-
-            let r = select.wait();
-
-            if r == tox_hdl.id() {
-                for e in tox.events() {
-                    self.tox_event(e);
-                }
-            }
-
-            if r == fdpoll_hdl.id() {
-                fdpoll.rcv.recv().ok();
-                // We're only watching stdin right now.
-                self.handle_key();
-                fdpoll.wait().ok();
-            }
-
-            if r == sig_hdl.id() {
-                for s in sigs.iter() {
-                    match s {
-                        Hangup | Terminate => self.shutdown = true,
-                        WinSize => self.resize(),
-                        _ => { /* ignore sigpipe */ },
-                    }
-                }
-            }
+    /// Gives the terminal back.
+    pub fn close(&mut self) {
+        nc::endwin();
+    }
 
-            if self.shutdown {
-                break;
-            }
+    /// Adds the friend `id` to the list together with what we know about them.
+    fn add_to_view(&mut self, session: &Session, id: i32) {
+        self.contacts.tox_add(id);
+        match session.tox.get_name(id) {
+            Some(ref name) if name.len() > 0 => {
+                self.contacts.tox_name_change(id, name.clone());
+            },
+            _ => { },
+        }
+        let client_id = match session.tox.get_client_id(id) {
+            Some(c) => c,
+            None => return,
+        };
+        match session.addressbook.get(&*client_id) {
+            Some(c) => {
+                self.contacts.set_alias(id, c.alias.clone());
+                self.contacts.set_note(id, c.note.clone());
+            },
+            None => { },
         }
-
-        fdpoll.abort().ok();
-        nc::endwin();
     }
 
-    fn tox_event(&mut self, e: Event) {
-        match e {
-            NameChange(i, name) => self.tox_name_change(i, name),
-            _ => { }
+    /// Tells the user about `msg` unless they're busy. Messages from the friend
+    /// `friend` are left out if we're chatting with them or they're muted.
+    fn notify(&mut self, session: &Session, friend: Option<i32>, from: &str, msg: &str) {
+        match session.tox.get_self_user_status() {
+            UserStatusBusy => return,
+            _ => { },
+        }
+        match friend {
+            Some(id) if self.contacts.showing_chat(id) || session.muted(id) => return,
+            _ => { },
+        }
+        match self.notifier.notify(from, msg) {
+            Err(e) => self.report(Error(e)),
+            _ => { },
         }
     }
 
-    fn tox_name_change(&mut self, id: i32, name: String) {
-        self.contacts.tox_name_change(id, name);
+    fn apply_config(&mut self, session: &mut Session, config: Config) {
+        use libc::{c_int};
+        extern {
+            fn set_escdelay(size: c_int) -> c_int;
+        }
+        unsafe { set_escdelay(config.escdelay as c_int); }
+        colors::init(config.colors.as_slice());
+        nc::bkgd(' ' as u32 | nc::COLOR_PAIR(COLOR_PAIR_DEFAULT) as u32);
+        session.configure(&config);
+        self.contacts.set_keymap(config.keymap);
+        self.notifier = config.notifier;
         self.needs_update = true;
     }
 
-    fn update(&mut self) {
-        if !self.needs_update {
-            return;
+    fn source(&mut self, session: &mut Session, path: Option<Path>) {
+        let path = path.unwrap_or_else(|| self.config.clone());
+        match config::load(&path) {
+            Ok(config) => {
+                self.apply_config(session, config);
+                session.emit(events::Info(format!("loaded {}", path.display())));
+            },
+            Err(s) => session.emit(events::Error(s)),
         }
-        self.needs_update = false;
-        self.update_statusline();
-        self.contacts.update();
-        normal!(COLOR_PAIR_DEFAULT);
     }
 
     fn update_statusline(&mut self) {
         nc::move(nc::LINES-1, 0);
-        normal!(COLOR_PAIR_STATUS);
+        if self.questions.len() > 0 {
+            normal!(COLOR_PAIR_WARNING);
+            nc::addstr(self.questions.get(0).ref1().as_slice());
+            nc::clrtoeol();
+            self.print_unread();
+            normal!(COLOR_PAIR_DEFAULT);
+            return;
+        }
         match self.status {
-            NoMsg => { },
-            Error(s) => {
-                nc::addstr(s);
+            NoMsg => { normal!(COLOR_PAIR_DEFAULT); },
+            Info(ref s) | Warning(ref s) | Error(ref s) => {
+                normal!(self.status.color());
+                nc::addstr(s.as_slice());
             },
         }
         nc::clrtoeol();
+        self.print_unread();
         normal!(COLOR_PAIR_DEFAULT);
     }
 
-    fn handle_key(&mut self) {
-        self.status = NoMsg;
-        match self.contacts.handle_key(nc::getch()) {
-            Some(c) => self.handle_command(c),
-            _ => { },
+    /// Prints the number of unread messages at the right end of the status line.
+    fn print_unread(&self) {
+        let (count, mentioned) = self.contacts.unread();
+        if count == 0 {
+            return;
         }
+        let text = format!(" {} unread", count);
+        // Writing to the last column would move the cursor off the screen.
+        let x = nc::COLS - 1 - text.len() as i32;
+        if x < 0 {
+            return;
+        }
+        if mentioned {
+            bold!(COLOR_PAIR_MENTION);
+        } else {
+            bold!(COLOR_PAIR_HEADER);
+        }
+        nc::mvaddstr(nc::LINES-1, x, text.as_slice());
+    }
+
+    /// Shows `msg` in the status line and adds it to the log.
+    fn report(&mut self, msg: StatusMessage) {
+        match msg {
+            Info(ref s) | Warning(ref s) | Error(ref s) => {
+                let time = time::now().strftime("%H:%M:%S");
+                self.log.push(format!("{} {}: {}", time, msg.level(), s));
+                if self.log.len() > MAX_LOG {
+                    self.log.remove(0);
+                }
+            },
+            NoMsg => { },
+        }
+        self.status_timeout = msg.timeout();
+        self.status = msg;
         self.needs_update = true;
     }
 
-    fn handle_command(&mut self, c: commands::Result) {
+    fn show_log(&mut self) {
+        let lines = self.log.clone();
+        self.contacts.show_pager("Messages".to_string(), lines);
+        self.contacts.scroll_pager_to_end();
+    }
+
+    /// Runs a command typed in command mode or bound to a key.
+    fn handle_command(&mut self, session: &mut Session, c: commands::Result) {
         let c = match c {
             Ok(c) => c,
             Err(s) => {
-                self.status = Error(s);
+                self.report(Error(s.to_string()));
                 return;
             },
         };
         match c {
-            Quit => self.shutdown = true,
-            Add(addr, msg) => self.tox_add(addr, msg),
-            Del(id) => self.tox_del(id),
+            Del(id) => self.confirm_del(session, id),
+            Help(topic) => self.help(topic),
+            c => match self.command(session, c) {
+                Some(c) => session.command(c, self.contacts.current_friend()),
+                None => { },
+            },
         }
     }
 
-    fn tox_add(&mut self, addr: Address, msg: String) {
-        let res = self.tox.add_friend(box addr, msg);
-        if res.is_ok() {
-            self.contacts.tox_add(res.ok().unwrap());
-        } else {
-            match res.unwrap_err() {
-                FaerrToolong => self.status = Error("message too long"),
-                FaerrOwnkey => self.status = Error("own key"),
-                FaerrAlreadysent => self.status = Error("already sent"),
-                FaerrBadchecksum => self.status = Error("bad checksum"),
-                _ => self.status = Error("unknown error"),
+    fn help(&mut self, topic: Option<String>) {
+        match commands::help(topic.as_ref().map(|t| t.as_slice())) {
+            Ok(lines) => {
+                let title = match topic {
+                    Some(t) => format!("Help: {}", t),
+                    None => "Help".to_string(),
+                };
+                self.contacts.show_pager(title, lines);
+            },
+            Err(s) => self.report(Error(s)),
+        }
+    }
+
+    /// Shows `text` in the status line until the user answers with y or n.
+    fn ask(&mut self, question: Question, text: String) {
+        self.questions.push((question, format!("{} (y/n)", text)));
+        self.needs_update = true;
+    }
+
+    fn answer(&mut self, session: &mut Session, question: Question, yes: bool) {
+        match (question, yes) {
+            (DeleteFriend(id), true) => session.command(Del(id), None),
+            (DeleteFriend(_), false) => self.report(Info("not deleted".to_string())),
+            (AcceptFile(id), true) => {
+                session.command(commands::File(commands::FileAccept, id), None);
+            },
+            (AcceptFile(id), false) => {
+                session.command(commands::File(commands::FileCancel, id), None);
+            },
+            (TakeCall(friend), yes) => session.answer(friend, yes),
+        }
+    }
+
+    /// Drops the first `n` questions if they're about what was selected. The user has
+    /// moved on.
+    fn drop_questions(&mut self, n: uint) {
+        let questions = mem::replace(&mut self.questions, Vec::new());
+        for (i, q) in questions.move_iter().enumerate() {
+            if i >= n || !q.ref0().about_focus() {
+                self.questions.push(q);
             }
         }
+    }
+
+    /// Asks whether we really want to delete the friend `id`.
+    fn confirm_del(&mut self, session: &Session, id: i32) {
+        if !session.tox.get_friendlist().contains(&id) {
+            self.report(Error(format!("unknown id {}", id)));
+            return;
+        }
+        let name = self.contacts.friend_name(id);
+        self.ask(DeleteFriend(id), format!("delete {}?", name));
+    }
+
+    fn copy_address(&mut self, session: &mut Session) {
+        let addr = session.tox.get_address().to_string();
+        session.emit(match term::set_clipboard(addr.as_slice()) {
+            Ok(_) => events::Info("address copied to the clipboard".to_string()),
+            Err(e) => events::Error(format!("could not copy the address: {}", e)),
+        });
+    }
+
+    /// Tells the user about a message from the friend `id` and shows it in the chat.
+    fn message(&mut self, session: &Session, id: i32, msg: &str, action: bool) {
+        let name = self.contacts.friend_name(id);
+        self.notify(session, Some(id), name.as_slice(), msg);
+        if action {
+            self.contacts.tox_action(id, msg.to_string());
+        } else {
+            self.contacts.tox_message(id, msg.to_string());
+        }
+    }
+}
+
+impl<'a> Frontend for Ui<'a> {
+    fn input(&mut self, session: &mut Session) {
+        let key = nc::getch();
+        // Questions are only answered in normal mode so that they don't get in the way
+        // of typing. Other keys go on to the view.
+        let answer = if key == 'y' as i32 || key == 'Y' as i32 {
+            Some(true)
+        } else if key == 'n' as i32 || key == 'N' as i32 || key == 27 {
+            Some(false)
+        } else {
+            None
+        };
+        match answer {
+            Some(yes) if self.questions.len() > 0 && self.contacts.in_normal_mode() => {
+                let (question, _) = self.questions.remove(0).unwrap();
+                self.answer(session, question, yes);
+                self.needs_update = true;
+                return;
+            },
+            _ => { },
+        }
+        let asked = self.questions.len();
+        let focus = self.contacts.focus();
+        match self.contacts.handle_key(key, &session.transfers) {
+            Some(c) => self.handle_command(session, c),
+            _ => { },
+        }
+        if self.contacts.focus() != focus {
+            self.drop_questions(asked);
+        }
+        match self.contacts.take_history_error() {
+            Some(e) => self.report(Error(e)),
+            None => { },
+        }
+        session.set_typing(self.contacts.typing_to());
         self.needs_update = true;
     }
 
-    fn tox_del(&mut self, id: i32) {
-        match self.contacts.del(id) {
-            Err(s) => self.status = Error(s),
-            _ => {
-                self.tox.del_friend(id).ok();
+    fn event(&mut self, session: &mut Session, e: &Event) {
+        match *e {
+            events::Ready(_) | events::HelpText(_) => { },
+            events::Info(ref s) | events::Address(ref s) => self.report(Info(s.clone())),
+            events::Warning(ref s) => self.report(Warning(s.clone())),
+            events::Error(ref s) => self.report(Error(s.clone())),
+            events::FriendAdded(id) => self.add_to_view(session, id),
+            events::RequestSent(_, ref addr) => {
+                self.contacts.remember_address(addr.clone());
+            },
+            events::FriendDeleted(id) => { self.contacts.del(id).ok(); },
+            events::NameChanged(id, ref name) => {
+                self.contacts.tox_name_change(id, name.clone());
             },
+            events::Message(id, _, ref msg) => {
+                self.message(session, id, msg.as_slice(), false);
+            },
+            events::Action(id, _, ref msg) => {
+                self.message(session, id, msg.as_slice(), true);
+            },
+            events::Sent(id, receipt, ref msg) => {
+                self.contacts.tox_sent(id, msg.clone(), receipt);
+            },
+            events::Delivered(id, receipt) => self.contacts.tox_read_receipt(id, receipt),
+            events::StatusMessageChanged(id, ref msg) => {
+                self.contacts.tox_status_message(id, msg.clone());
+            },
+            events::UserStatusChanged(id, status) => {
+                self.contacts.tox_user_status(id, status);
+            },
+            events::Connection(id, online) => {
+                self.contacts.tox_connection_status(id, online);
+            },
+            events::Typing(id, typing) => self.contacts.tox_typing(id, typing),
+            events::Request(n, ref client_id, ref msg) => {
+                let from = format!("friend request from {}",
+                                   client_id.as_slice().slice_to(8));
+                self.notify(session, None, from.as_slice(), msg.as_slice());
+                self.contacts.tox_request(n, client_id.clone(), msg.clone());
+            },
+            events::RequestAnswered(n) => { self.contacts.del_request(n).ok(); },
+            events::AliasChanged(id, ref alias) => {
+                self.contacts.set_alias(id, alias.clone());
+            },
+            events::NoteChanged(id, ref note) => self.contacts.set_note(id, note.clone()),
+            events::GroupAdded(group) => self.contacts.tox_add_group(group),
+            events::GroupLeft(group) => { self.contacts.del_group(group).ok(); },
+            events::GroupInvite(id) => self.contacts.tox_group_invite(id),
+            events::GroupMessage(group, _, ref name, ref msg) => {
+                self.contacts.tox_group_message(group, name.as_slice(), msg.clone());
+            },
+            events::GroupPeers(group, ref peers) => {
+                self.contacts.tox_group_peers(group, peers.clone());
+            },
+            events::SelfChanged(ref name, ref msg, status) => {
+                self.contacts.set_self(name.clone(), msg.clone(), status);
+            },
+            events::FileOffer(id, friend, ref name, size) => {
+                let size = fmt_size(size);
+                let msg = format!("offers {} ({}), see :transfers", name, size);
+                self.contacts.tox_action(friend, msg);
+                let peer = self.contacts.friend_name(friend);
+                let text = format!("save {} ({}) from {}?", name, size, peer);
+                self.ask(AcceptFile(id), text);
+            },
+            events::TransferChanged(id, _) => {
+                // Answered in the transfers pane or cancelled by the sender.
+                self.questions.retain(|&(q, _)| match q {
+                    AcceptFile(t) => t != id,
+                    _ => true,
+                });
+            },
+            events::CallChanged(friend, call) => {
+                self.contacts.set_call(friend, call);
+                // Answered with a command or given up by the caller.
+                self.questions.retain(|&(q, _)| match q {
+                    TakeCall(f) => f != friend,
+                    _ => true,
+                });
+                if call == Some(Ringing) {
+                    let msg = "is calling (:answer or :hangup)".to_string();
+                    self.contacts.tox_action(friend, msg);
+                    let name = self.contacts.friend_name(friend);
+                    self.ask(TakeCall(friend), format!("answer the call of {}?", name));
+                }
+            },
+        }
+        self.needs_update = true;
+    }
+
+    /// Runs the commands that need the terminal.
+    fn command(&mut self, session: &mut Session, c: Command) -> Option<Command> {
+        match c {
+            Source(path) => self.source(session, path),
+            Messages => self.show_log(),
+            ShowTransfers => self.contacts.show_transfers(),
+            MyId(ToClipboard) => self.copy_address(session),
+            c => return Some(c),
+        }
+        self.needs_update = true;
+        None
+    }
+
+    fn current_friend(&self) -> Option<i32> {
+        self.contacts.current_friend()
+    }
+
+    fn tick(&mut self, session: &Session) {
+        if session.transfers.active() {
+            self.needs_update = true;
+        }
+        if self.status_timeout == 0 {
+            return;
+        }
+        self.status_timeout -= 1;
+        if self.status_timeout == 0 {
+            self.status = NoMsg;
+            self.needs_update = true;
         }
     }
 
@@ -251,5 +533,14 @@ impl<'a> Ui<'a> {
         self.contacts.resize();
         self.needs_update = true;
     }
-}
 
+    fn update(&mut self, session: &Session) {
+        if !self.needs_update {
+            return;
+        }
+        self.needs_update = false;
+        self.update_statusline();
+        self.contacts.update(&session.transfers);
+        normal!(COLOR_PAIR_DEFAULT);
+    }
+}